@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{
+    App, CellId,
+    deps::{Area, SheetCell},
+    formula::{self, CellSource},
+    sheets::SheetId,
+    value::{CellError, Value},
+};
+
+impl App {
+    /// Value of a cell as of the last recalculation.
+    pub(super) fn cell_value(&self, cell: CellId) -> Value {
+        self.computed.get(cell).cloned().unwrap_or(Value::Empty)
+    }
+
+    /// Stores the raw contents of a cell and recomputes only the cells that
+    /// depend on it.
+    pub(super) fn set_cell(&mut self, cell: CellId, raw: String) {
+        self.set_cells(vec![(cell, raw)]);
+    }
+
+    /// Applies several writes as one undoable step, in order, so a cell may
+    /// be cleared and refilled within the same batch.
+    pub(super) fn set_cells(&mut self, writes: Vec<(CellId, String)>) {
+        let opened = self.history.begin(self.cursor);
+        let mut changed = Vec::with_capacity(writes.len());
+        for (cell, raw) in writes {
+            let after = (!raw.is_empty()).then_some(raw);
+            let before = self.write_raw(cell, after.clone());
+            if before != after {
+                self.history.record(cell, before, after);
+                changed.push(cell);
+            }
+        }
+        if opened {
+            self.history.commit();
+        }
+        self.recalculate(&changed);
+    }
+
+    /// Writes cells without recording them, for replaying history.
+    pub(super) fn restore_cells(&mut self, cells: Vec<(CellId, Option<String>)>) {
+        let mut changed = Vec::with_capacity(cells.len());
+        for (cell, raw) in cells {
+            self.write_raw(cell, raw);
+            changed.push(cell);
+        }
+        self.recalculate(&changed);
+    }
+
+    fn write_raw(&mut self, cell: CellId, raw: Option<String>) -> Option<String> {
+        let before = match raw.clone() {
+            Some(raw) => self.cells.insert(cell, raw),
+            None => self.cells.remove(&cell),
+        };
+        self.modified |= before != raw;
+        before
+    }
+
+    /// Rebuilds the dependency graph and every computed value across the
+    /// workbook, for edits that move many cells at once.
+    pub(super) fn recalculate_all(&mut self) {
+        self.dependencies.clear();
+        self.cycles.clear();
+        self.computed.clear();
+        for sheet in &mut self.sheets {
+            sheet.computed.clear();
+        }
+        let ids: Vec<SheetId> = self.sheets.iter().map(|sheet| sheet.id).collect();
+        let mut nodes = Vec::new();
+        for id in ids {
+            let cells = self.cells_of(id).into_iter().flat_map(HashMap::keys);
+            nodes.extend(cells.map(|cell| SheetCell::new(id, *cell)));
+        }
+        self.link_precedents(&nodes);
+        // Every cell is affected, so there is no need to follow readers.
+        self.evaluate(&nodes.into_iter().collect());
+    }
+
+    /// Recomputes the cells of the active sheet in `changed` and every
+    /// formula that reads them, on whichever sheet it lives.
+    pub(super) fn recalculate(&mut self, changed: &[CellId]) {
+        let sheet = self.sheet_id();
+        let changed: Vec<SheetCell> = changed
+            .iter()
+            .map(|cell| SheetCell::new(sheet, *cell))
+            .collect();
+        self.link_precedents(&changed);
+        let affected = self.dependencies.affected_by(&changed);
+        self.cycles
+            .retain(|chain| !chain.iter().any(|node| affected.contains(node)));
+        self.evaluate(&affected);
+    }
+
+    fn link_precedents(&mut self, nodes: &[SheetCell]) {
+        for node in nodes {
+            let areas = self.precedents_of(*node);
+            self.dependencies.set_precedents(*node, areas);
+        }
+    }
+
+    /// Recomputes `affected` in dependency order.
+    fn evaluate(&mut self, affected: &HashSet<SheetCell>) {
+        let (order, blocked) = self.dependencies.evaluation_order(affected);
+        for node in order {
+            let value = self.compute(node);
+            self.store_value(node, value);
+        }
+        if !blocked.is_empty() {
+            self.resolve_cycles(&blocked);
+        }
+    }
+
+    /// Marks every cell on a reference cycle with `#CYCLE!`, then evaluates
+    /// the cells that were only waiting on those cycles.
+    fn resolve_cycles(&mut self, blocked: &[SheetCell]) {
+        let chains = self.dependencies.cycles(blocked);
+        let in_cycle: HashSet<SheetCell> = chains.iter().flatten().copied().collect();
+        for node in &in_cycle {
+            self.store_value(*node, Value::Error(CellError::Cycle));
+        }
+
+        let downstream: HashSet<SheetCell> = blocked
+            .iter()
+            .filter(|node| !in_cycle.contains(node))
+            .copied()
+            .collect();
+        let (order, _) = self.dependencies.evaluation_order(&downstream);
+        for node in order {
+            let value = self.compute(node);
+            self.store_value(node, value);
+        }
+
+        self.cycles.extend(chains);
+    }
+
+    fn raw_of(&self, node: SheetCell) -> &str {
+        self.cells_of(node.sheet)
+            .and_then(|cells| cells.get(&node.cell))
+            .map(String::as_str)
+            .unwrap_or("")
+    }
+
+    /// The areas `node` reads. References to sheets that do not exist read
+    /// nothing; they evaluate to `#REF!` until such a sheet is added.
+    fn precedents_of(&self, node: SheetCell) -> Vec<Area> {
+        let Some(body) = formula::formula_body(self.raw_of(node)) else {
+            return Vec::new();
+        };
+        match formula::parse(body) {
+            Ok(expr) => expr
+                .references()
+                .into_iter()
+                .filter_map(|(name, from, to)| {
+                    let sheet = match name {
+                        Some(name) => self.sheet_named(name)?,
+                        None => node.sheet,
+                    };
+                    Some(Area::new(sheet, from, to))
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn compute(&self, node: SheetCell) -> Value {
+        let raw = self.raw_of(node);
+        let Some(body) = formula::formula_body(raw) else {
+            return Value::from_literal(raw);
+        };
+
+        let value = match formula::parse(body) {
+            Ok(expr) => formula::evaluate(
+                &expr,
+                &Computed {
+                    app: self,
+                    sheet: node.sheet,
+                },
+            ),
+            Err(err) => Value::Error(err),
+        };
+        match value {
+            Value::Empty => Value::Number(0.0),
+            other => other,
+        }
+    }
+
+    fn store_value(&mut self, node: SheetCell, value: Value) {
+        let Some(values) = self.values_of_mut(node.sheet) else {
+            return;
+        };
+        if value == Value::Empty {
+            values.remove(node.cell);
+        } else {
+            values.insert(node.cell, value);
+        }
+    }
+}
+
+/// Serves formula lookups from the values cached by earlier recalculations,
+/// with unqualified references read from `sheet`.
+struct Computed<'a> {
+    app: &'a App,
+    sheet: SheetId,
+}
+
+impl Computed<'_> {
+    fn values(&self) -> Option<&Values> {
+        self.app.values_of(self.sheet)
+    }
+}
+
+impl CellSource for Computed<'_> {
+    fn cell_value(&self, cell: CellId) -> Value {
+        self.values()
+            .and_then(|values| values.get(cell))
+            .cloned()
+            .unwrap_or(Value::Empty)
+    }
+
+    fn range_values(&self, from: CellId, to: CellId) -> Vec<Value> {
+        self.values()
+            .map(|values| values.range(from, to))
+            .unwrap_or_default()
+    }
+
+    fn sheet_range_values(
+        &self,
+        sheet: &str,
+        from: CellId,
+        to: CellId,
+    ) -> Result<Vec<Value>, CellError> {
+        self.app
+            .sheet_named(sheet)
+            .and_then(|sheet| self.app.values_of(sheet))
+            .map(|values| values.range(from, to))
+            .ok_or(CellError::Ref)
+    }
+}
+
+/// Computed values filed by column and then by row, so reading a range
+/// walks only the cells inside it, already in order.
+#[derive(Debug, Default)]
+pub(crate) struct Values {
+    columns: HashMap<usize, BTreeMap<usize, Value>>,
+    /// Values handed out by `range`, so tests can check how much a
+    /// recalculation reads.
+    #[cfg(test)]
+    read: std::cell::Cell<usize>,
+}
+
+impl Values {
+    pub(crate) fn get(&self, cell: CellId) -> Option<&Value> {
+        self.columns.get(&cell.col)?.get(&cell.row)
+    }
+
+    fn insert(&mut self, cell: CellId, value: Value) {
+        self.columns
+            .entry(cell.col)
+            .or_default()
+            .insert(cell.row, value);
+    }
+
+    fn remove(&mut self, cell: CellId) {
+        if let Some(column) = self.columns.get_mut(&cell.col) {
+            column.remove(&cell.row);
+            if column.is_empty() {
+                self.columns.remove(&cell.col);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.columns.clear();
+    }
+
+    /// Values in the rectangle `from..=to`, in row-major order.
+    fn range(&self, from: CellId, to: CellId) -> Vec<Value> {
+        let values = self.collect_range(from, to);
+        #[cfg(test)]
+        self.read.set(self.read.get() + values.len());
+        values
+    }
+
+    fn collect_range(&self, from: CellId, to: CellId) -> Vec<Value> {
+        let rows = from.row..=to.row;
+        if from.col == to.col {
+            return self
+                .columns
+                .get(&from.col)
+                .into_iter()
+                .flat_map(|column| column.range(rows.clone()))
+                .map(|(_, value)| value.clone())
+                .collect();
+        }
+
+        let mut cells: Vec<(usize, usize, &Value)> = self
+            .columns
+            .iter()
+            .filter(|(col, _)| (from.col..=to.col).contains(*col))
+            .flat_map(|(col, column)| {
+                column
+                    .range(rows.clone())
+                    .map(|(row, value)| (*row, *col, value))
+            })
+            .collect();
+        cells.sort_by_key(|(row, col, _)| (*row, *col));
+        cells
+            .into_iter()
+            .map(|(_, _, value)| value.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{App, CellId, deps::SheetCell, value::Value};
+
+    /// A column of ones and, next to it, a column of running sums over it.
+    fn running_sums(rows: usize) -> App {
+        let mut app = App::new();
+        for row in 0..rows {
+            app.cells.insert(CellId::new(row, 0), "1".to_string());
+            app.cells
+                .insert(CellId::new(row, 1), format!("=SUM(A1:A{})", row + 1));
+        }
+        app
+    }
+
+    /// Cells read by the sums on rows `first..rows`, each of which reads
+    /// every row up to its own.
+    fn sum_reads(first: usize, rows: usize) -> usize {
+        (first..rows).map(|row| row + 1).sum()
+    }
+
+    #[test]
+    fn sums_read_only_their_ranges_once() {
+        let rows = 2_000;
+        let mut app = running_sums(rows);
+        app.recalculate_all();
+        let last = CellId::new(rows - 1, 1);
+        assert_eq!(app.cell_value(last), Value::Number(rows as f64));
+        assert_eq!(app.computed.read.get(), sum_reads(0, rows));
+
+        app.computed.read.set(0);
+        let middle = rows / 2;
+        app.set_cell(CellId::new(middle, 0), "2".to_string());
+        assert_eq!(app.computed.read.get(), sum_reads(middle, rows));
+        assert_eq!(app.cell_value(last), Value::Number(rows as f64 + 1.0));
+        assert_eq!(
+            app.cell_value(CellId::new(middle - 1, 1)),
+            Value::Number(middle as f64)
+        );
+    }
+
+    #[test]
+    fn edits_only_recompute_readers() {
+        let mut app = running_sums(10);
+        app.recalculate_all();
+        let changed = SheetCell::new(app.sheet_id(), CellId::new(7, 0));
+        let affected = app.dependencies.affected_by(&[changed]);
+        let mut rows: Vec<(usize, usize)> = affected
+            .iter()
+            .map(|node| (node.cell.row, node.cell.col))
+            .collect();
+        rows.sort();
+        assert_eq!(rows, vec![(7, 0), (7, 1), (8, 1), (9, 1)]);
+    }
+}
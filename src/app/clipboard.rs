@@ -0,0 +1,337 @@
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+use super::{
+    App, CellId,
+    csv::{escape_field, parse_delimited},
+    formula,
+};
+
+/// Yanked cells, kept as raw values so formulas survive the round trip.
+#[derive(Debug, Clone)]
+pub(crate) struct Clipboard {
+    pub(crate) rows: Vec<Vec<String>>,
+    /// Top-left cell the block was yanked from, used to shift relative
+    /// references on paste.
+    pub(crate) origin: CellId,
+    pub(crate) kind: ClipboardKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ClipboardKind {
+    /// A rectangle pasted over the cells at the cursor.
+    Block,
+    /// Whole rows, pasted as new rows below or above the cursor like vim's
+    /// linewise registers.
+    Rows,
+}
+
+impl Clipboard {
+    pub(crate) fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    pub(crate) fn cell_count(&self) -> usize {
+        self.rows.iter().map(Vec::len).sum()
+    }
+}
+
+impl App {
+    /// Raw values of the block between two corners, in row-major order.
+    pub(super) fn block_values(&self, from: CellId, to: CellId) -> Vec<Vec<String>> {
+        (from.row..=to.row)
+            .map(|row| {
+                (from.col..=to.col)
+                    .map(|col| {
+                        self.cells
+                            .get(&CellId::new(row, col))
+                            .cloned()
+                            .unwrap_or_default()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Mirrors the internal clipboard to the system one as TSV of the
+    /// displayed values, which is what other applications expect.
+    pub(super) fn publish_clipboard(&mut self) {
+        let Some(clip) = self.clipboard.as_ref() else {
+            return;
+        };
+        let rows: Vec<Vec<String>> = (0..clip.height())
+            .map(|row_offset| {
+                (0..clip.rows[row_offset].len())
+                    .map(|col_offset| {
+                        self.get_cell_display_text(
+                            clip.origin.row + row_offset,
+                            clip.origin.col + col_offset,
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        if let Err(err) = self.clipboard_backend.copy(&to_tsv(&rows)) {
+            self.command_buffer = format!("clipboard error: {}", err);
+        }
+    }
+
+    /// Pastes a grid read from the system clipboard at the cursor, for `"+p`.
+    pub(super) fn paste_from_system_clipboard(&mut self) {
+        let rows = match self
+            .clipboard_backend
+            .paste()
+            .and_then(|text| parse_grid(&text))
+        {
+            Ok(rows) if !rows.is_empty() => rows,
+            Ok(_) => {
+                self.command_buffer = "system clipboard empty".to_string();
+                return;
+            }
+            Err(err) => {
+                self.command_buffer = format!("clipboard error: {}", err);
+                return;
+            }
+        };
+        let target = CellId::new(self.cursor.row, self.cursor.col);
+        let clip = Clipboard {
+            rows,
+            origin: target,
+            kind: ClipboardKind::Block,
+        };
+        self.paste_block_at(&clip, target);
+        self.command_buffer = format!("{} cells pasted", clip.cell_count());
+    }
+
+    /// Writes `clipboard` with its top-left corner at `target`, shifting
+    /// relative references by the distance from where it was yanked.
+    pub(super) fn paste_block_at(&mut self, clipboard: &Clipboard, target: CellId) {
+        let writes = paste_writes(clipboard, target);
+        self.set_cells(writes);
+    }
+}
+
+/// The cell writes that paste `clipboard` with its top-left on `target`.
+pub(crate) fn paste_writes(clipboard: &Clipboard, target: CellId) -> Vec<(CellId, String)> {
+    let delta_row = target.row as isize - clipboard.origin.row as isize;
+    let delta_col = target.col as isize - clipboard.origin.col as isize;
+    let mut writes = Vec::with_capacity(clipboard.cell_count());
+    for (row_offset, row) in clipboard.rows.iter().enumerate() {
+        for (col_offset, value) in row.iter().enumerate() {
+            let cell = CellId::new(target.row + row_offset, target.col + col_offset);
+            writes.push((cell, formula::translate(value, delta_row, delta_col)));
+        }
+    }
+    writes
+}
+
+/// Where yanks are mirrored outside the process, set with `:set clipboard=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ClipboardBackend {
+    /// Terminal escape sequence, works through SSH but cannot be read back.
+    /// Skipped when stdout is not a terminal, as under tests.
+    Osc52,
+    Xclip,
+    WlCopy,
+    Off,
+}
+
+impl ClipboardBackend {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "osc52" => Some(Self::Osc52),
+            "xclip" => Some(Self::Xclip),
+            "wl-copy" | "wayland" => Some(Self::WlCopy),
+            "off" | "none" => Some(Self::Off),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Osc52 => "osc52",
+            Self::Xclip => "xclip",
+            Self::WlCopy => "wl-copy",
+            Self::Off => "off",
+        }
+    }
+
+    pub(crate) fn copy(&self, text: &str) -> Result<(), String> {
+        match self {
+            Self::Osc52 => {
+                let mut stdout = io::stdout();
+                if !stdout.is_terminal() {
+                    return Ok(());
+                }
+                write!(stdout, "\x1b]52;c;{}\x07", base64_encode(text.as_bytes()))
+                    .and_then(|_| stdout.flush())
+                    .map_err(|err| err.to_string())
+            }
+            Self::Xclip => pipe_into("xclip", &["-selection", "clipboard"], text),
+            Self::WlCopy => pipe_into("wl-copy", &[], text),
+            Self::Off => Ok(()),
+        }
+    }
+
+    /// Reads the system clipboard. OSC 52 reads are disabled by most
+    /// terminals, so that backend falls back to whichever tool the session
+    /// has a display for.
+    pub(crate) fn paste(&self) -> Result<String, String> {
+        match self {
+            Self::Xclip => read_from("xclip", &["-selection", "clipboard", "-o"]),
+            Self::WlCopy => read_from("wl-paste", &["--no-newline"]),
+            Self::Osc52 | Self::Off => {
+                if env::var_os("WAYLAND_DISPLAY").is_some() {
+                    read_from("wl-paste", &["--no-newline"])
+                } else if env::var_os("DISPLAY").is_some() {
+                    read_from("xclip", &["-selection", "clipboard", "-o"])
+                } else {
+                    Err("no readable system clipboard".to_string())
+                }
+            }
+        }
+    }
+}
+
+fn pipe_into(program: &str, args: &[&str], text: &str) -> Result<(), String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("{}: {}", program, err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|err| format!("{}: {}", program, err))?;
+    }
+    let status = child
+        .wait()
+        .map_err(|err| format!("{}: {}", program, err))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", program, status))
+    }
+}
+
+fn read_from(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|err| format!("{}: {}", program, err))?;
+    if !output.status.success() {
+        return Err(format!("{} exited with {}", program, output.status));
+    }
+    String::from_utf8(output.stdout).map_err(|err| format!("{}: {}", program, err))
+}
+
+/// Splits text pasted from elsewhere into a grid. Tab separated text is
+/// what spreadsheets and browser tables put on the clipboard, and what
+/// `to_tsv` writes. Commas are not separators, so `Smith, John` or
+/// `1,234.56` stays one cell.
+pub(crate) fn parse_grid(text: &str) -> Result<Vec<Vec<String>>, String> {
+    parse_delimited(text, '\t')
+}
+
+pub(crate) fn to_tsv(rows: &[Vec<String>]) -> String {
+    let lines: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| escape_field(value, '\t'))
+                .collect::<Vec<String>>()
+                .join("\t")
+        })
+        .collect();
+    lines.join("\n")
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        out.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 {
+            ALPHABET[(n >> 6) as usize & 63] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            ALPHABET[n as usize & 63] as char
+        } else {
+            '='
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_encode, parse_grid, to_tsv};
+
+    fn grid(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|field| field.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn base64_matches_the_rfc_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (plain, encoded) in vectors {
+            assert_eq!(base64_encode(plain.as_bytes()), encoded, "{:?}", plain);
+        }
+        assert_eq!(base64_encode(&[0xff, 0xfe, 0x00]), "//4A");
+    }
+
+    #[test]
+    fn tsv_round_trips_through_parse_grid() {
+        let grids = [
+            grid(&[&["1", "=A1*2", "say \"hi\""], &["two\nlines", "", "a\tb"]]),
+            grid(&[&["Smith, John"], &["1,234.56"]]),
+            grid(&[&["only"]]),
+            grid(&[&["\"quoted\nnewline\""]]),
+        ];
+        for rows in grids {
+            assert_eq!(parse_grid(&to_tsv(&rows)).unwrap(), rows);
+        }
+    }
+
+    #[test]
+    fn commas_do_not_split_cells() {
+        assert_eq!(
+            parse_grid("Smith, John").unwrap(),
+            grid(&[&["Smith, John"]])
+        );
+        assert_eq!(
+            parse_grid("1,234.56\t2\n3\t4\n").unwrap(),
+            grid(&[&["1,234.56", "2"], &["3", "4"]])
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use ratatui::text::Line;
+
+use super::{App, render::column_name};
+
+const DEFAULT_COLUMN_WIDTH: u16 = 12;
+const MIN_COLUMN_WIDTH: u16 = 3;
+const MAX_COLUMN_WIDTH: u16 = 120;
+
+impl App {
+    pub(super) fn column_width(&self, col: usize) -> u16 {
+        self.column_widths
+            .get(&col)
+            .copied()
+            .unwrap_or(DEFAULT_COLUMN_WIDTH)
+    }
+
+    /// Sets the width of `col`, clamped to a usable range. Columns at the
+    /// default width are not stored.
+    pub(super) fn set_column_width(&mut self, col: usize, width: u16) {
+        let width = width.clamp(MIN_COLUMN_WIDTH, MAX_COLUMN_WIDTH);
+        self.modified |= width != self.column_width(col);
+        if width == DEFAULT_COLUMN_WIDTH {
+            self.column_widths.remove(&col);
+        } else {
+            self.column_widths.insert(col, width);
+        }
+    }
+
+    pub(super) fn resize_current_column(&mut self, delta: i16) {
+        let col = self.cursor.col;
+        let width = self.column_width(col).saturating_add_signed(delta);
+        self.set_column_width(col, width);
+        self.command_buffer = format!(
+            "column {} width {}",
+            column_name(col),
+            self.column_width(col)
+        );
+    }
+
+    /// Sizes `col` to its widest displayed value, leaving one blank cell of
+    /// padding so neighbouring values do not run together.
+    pub(super) fn autofit_column(&mut self, col: usize) {
+        self.fit_column(col);
+        self.command_buffer = format!(
+            "column {} width {}",
+            column_name(col),
+            self.column_width(col)
+        );
+    }
+
+    /// Fits every column that holds a value.
+    pub(super) fn autofit_all_columns(&mut self) {
+        let mut cols: Vec<usize> = self.cells.keys().map(|cell| cell.col).collect();
+        cols.sort_unstable();
+        cols.dedup();
+        for col in &cols {
+            self.fit_column(*col);
+        }
+        self.command_buffer = format!("{} column(s) fitted", cols.len());
+    }
+
+    fn fit_column(&mut self, col: usize) {
+        let widest = self
+            .cells
+            .keys()
+            .filter(|cell| cell.col == col)
+            .map(|cell| Line::from(self.get_cell_display_text(cell.row, cell.col)).width())
+            .chain(std::iter::once(column_name(col).len()))
+            .max()
+            .unwrap_or(0);
+        let width = u16::try_from(widest + 1).unwrap_or(u16::MAX);
+        self.set_column_width(col, width);
+    }
+
+    /// How many whole columns starting at `start` fit in the grid. A column
+    /// wider than the grid still counts, so the cursor is never hidden.
+    pub(super) fn columns_fitting(&self, start: usize) -> usize {
+        if self.grid_width == 0 {
+            return 0;
+        }
+        let mut used = 0;
+        let mut count = 0;
+        loop {
+            used += self.column_width(start + count);
+            if used > self.grid_width {
+                return count.max(1);
+            }
+            count += 1;
+        }
+    }
+
+    /// The leftmost viewport column that still shows `col` at the right
+    /// edge of the grid.
+    pub(super) fn first_column_showing(&self, col: usize) -> usize {
+        let mut start = col;
+        let mut used = self.column_width(col);
+        while start > 0 {
+            used += self.column_width(start - 1);
+            if used > self.grid_width {
+                break;
+            }
+            start -= 1;
+        }
+        start
+    }
+
+    /// Moves stored widths along with their columns when `count` columns
+    /// are inserted before `at`.
+    pub(super) fn insert_column_widths(&mut self, at: usize, count: usize) {
+        self.column_widths = self
+            .column_widths
+            .drain()
+            .map(|(col, width)| {
+                if col >= at {
+                    (col + count, width)
+                } else {
+                    (col, width)
+                }
+            })
+            .collect();
+    }
+
+    /// Drops the widths of the deleted columns `at..at + count` and pulls
+    /// the following ones back.
+    pub(super) fn delete_column_widths(&mut self, at: usize, count: usize) {
+        let end = at + count;
+        self.column_widths = self
+            .column_widths
+            .drain()
+            .filter_map(|(col, width)| match col {
+                col if col < at => Some((col, width)),
+                col if col < end => None,
+                col => Some((col - count, width)),
+            })
+            .collect();
+    }
+}
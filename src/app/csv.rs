@@ -0,0 +1,129 @@
+pub(crate) fn csv_escape(value: &str) -> String {
+    escape_field(value, ',')
+}
+
+/// Quotes a field if it contains the delimiter, a quote or a line break.
+pub(crate) fn escape_field(value: &str, delimiter: char) -> String {
+    if value.contains(['"', delimiter, '\n', '\r']) {
+        let escaped = value.replace('"', "\"\"");
+        format!("\"{}\"", escaped)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Splits CSV text into rows of fields, undoing `csv_escape`: quoted fields
+/// may hold commas, newlines and doubled quotes.
+pub(crate) fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    parse_delimited(text, ',')
+}
+
+pub(crate) fn parse_delimited(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => {
+                let start_line = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => return Err(format!("unterminated quote on line {}", start_line)),
+                    }
+                }
+                match chars.peek() {
+                    None | Some('\n') | Some('\r') => {}
+                    Some(c) if *c == delimiter => {}
+                    Some(_) => return Err(format!("unexpected text after quote on line {}", line)),
+                }
+            }
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+                line += 1;
+            }
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_escape, escape_field, parse_csv, parse_delimited};
+
+    const AWKWARD: [&str; 9] = [
+        "plain",
+        "with, comma",
+        "say \"hi\"",
+        "\"",
+        "two\nlines",
+        "carriage\rreturn",
+        "windows\r\nbreak",
+        "=SUM(A1:A3)",
+        " padded ",
+    ];
+
+    #[test]
+    fn escaped_values_parse_back_unchanged() {
+        for value in AWKWARD {
+            let parsed = parse_csv(&csv_escape(value)).unwrap();
+            assert_eq!(parsed, vec![vec![value.to_string()]], "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn escaped_tables_parse_back_unchanged() {
+        let rows: Vec<Vec<String>> = vec![
+            AWKWARD.iter().map(|value| value.to_string()).collect(),
+            vec![String::new(), "after empty".to_string(), String::new()],
+        ];
+        for delimiter in [',', '\t', ';'] {
+            let text: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    let fields: Vec<String> = row
+                        .iter()
+                        .map(|field| escape_field(field, delimiter))
+                        .collect();
+                    fields.join(&delimiter.to_string())
+                })
+                .collect();
+            let parsed = parse_delimited(&text.join("\r\n"), delimiter).unwrap();
+            assert_eq!(parsed, rows, "{:?}", delimiter);
+        }
+    }
+
+    #[test]
+    fn broken_quotes_are_reported_with_their_line() {
+        assert_eq!(
+            parse_csv("a\n\"open,b"),
+            Err("unterminated quote on line 2".to_string())
+        );
+        assert_eq!(
+            parse_csv("\"x\"y"),
+            Err("unexpected text after quote on line 1".to_string())
+        );
+    }
+}
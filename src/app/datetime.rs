@@ -0,0 +1,313 @@
+//! Dates and times as serial numbers: whole days since 1899-12-30, the
+//! epoch other spreadsheets use, plus the time of day as a fraction.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const DAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Which parts of a date/time a value was entered with, so it can be shown
+/// back the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DateKind {
+    Date,
+    Time,
+    DateTime,
+}
+
+/// Parses `2024-03-05`, `14:30`, `14:30:15`, `2024-03-05 14:30` or
+/// `2024-03-05T14:30:15` into a serial number.
+pub(crate) fn parse(raw: &str) -> Option<(f64, DateKind)> {
+    let raw = raw.trim();
+    if let Some((date, time)) = raw.split_once(['T', ' ']) {
+        let serial = parse_date(date)? + parse_time(time.trim_start())?;
+        return Some((serial, DateKind::DateTime));
+    }
+    if let Some(serial) = parse_date(raw) {
+        return Some((serial, DateKind::Date));
+    }
+    parse_time(raw).map(|serial| (serial, DateKind::Time))
+}
+
+pub(crate) fn format(serial: f64, kind: DateKind) -> String {
+    let (days, seconds) = split_serial(serial);
+    let (year, month, day) = civil_from_days(days);
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+    let time = match seconds % 60 {
+        0 => format!("{:02}:{:02}", seconds / 3600, seconds / 60 % 60),
+        secs => format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, secs),
+    };
+    match kind {
+        DateKind::Date => date,
+        DateKind::Time => time,
+        DateKind::DateTime => format!("{} {}", date, time),
+    }
+}
+
+/// Formats a serial with a strftime-like pattern: `%Y %y %m %d %e %b %B
+/// %a %A %H %M %S %%`. Anything else is copied as is.
+pub(crate) fn format_pattern(serial: f64, pattern: &str) -> String {
+    let (days, seconds) = split_serial(serial);
+    let (year, month, day) = civil_from_days(days);
+    let month_name = MONTH_NAMES[month as usize - 1];
+    let day_name = DAY_NAMES[weekday(days)];
+
+    let mut out = String::with_capacity(pattern.len() + 8);
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('y') => out.push_str(&format!("{:02}", year.rem_euclid(100))),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('e') => out.push_str(&day.to_string()),
+            Some('b') => out.push_str(&month_name[..3]),
+            Some('B') => out.push_str(month_name),
+            Some('a') => out.push_str(&day_name[..3]),
+            Some('A') => out.push_str(day_name),
+            Some('H') => out.push_str(&format!("{:02}", seconds / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", seconds / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", seconds % 60)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Whether a serial falls between years 1 and 9999, the dates that can be
+/// shown.
+pub(crate) fn in_range(serial: f64) -> bool {
+    (MIN_SERIAL..MAX_SERIAL).contains(&serial)
+}
+
+/// The current date and time as a serial, in UTC: the local time zone is
+/// not applied, so `TODAY()` turns over at midnight UTC.
+pub(crate) fn now() -> f64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs_f64())
+        .unwrap_or(0.0);
+    UNIX_EPOCH_SERIAL as f64 + seconds / SECONDS_PER_DAY
+}
+
+/// Year, month and day of the date part of a serial.
+pub(crate) fn date_parts(serial: f64) -> (i64, u32, u32) {
+    civil_from_days(split_serial(serial).0)
+}
+
+/// Serial of a date, rolling months past 12 into later years and days past
+/// the end of the month into later months, as `DATE` does.
+pub(crate) fn serial_from_parts(year: i64, month: i64, day: i64) -> f64 {
+    let year = year + (month - 1).div_euclid(12);
+    let month = (month - 1).rem_euclid(12) as u32 + 1;
+    (days_from_civil(year, month, 1) + day - 1) as f64
+}
+
+/// Serial of the last day of the month `months` after the one `serial`
+/// falls in.
+pub(crate) fn end_of_month(serial: f64, months: i64) -> f64 {
+    let (year, month, _) = date_parts(serial);
+    serial_from_parts(year, i64::from(month) + months + 1, 0)
+}
+
+/// Whether the day a serial falls on is a Saturday or Sunday.
+pub(crate) fn is_weekend(serial: f64) -> bool {
+    weekday(split_serial(serial).0) >= 5
+}
+
+/// Day of the week with Monday as 0. The epoch was a Saturday.
+fn weekday(days: i64) -> usize {
+    (days + 5).rem_euclid(7) as usize
+}
+
+/// Serial number of a calendar date, or `None` if the date does not exist.
+pub(crate) fn serial_from_date(year: i64, month: u32, day: u32) -> Option<f64> {
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) as f64)
+}
+
+fn parse_date(raw: &str) -> Option<f64> {
+    let mut parts = raw.splitn(3, '-');
+    let year = parts.next().filter(|part| part.len() == 4)?;
+    let month = parts.next().filter(|part| (1..=2).contains(&part.len()))?;
+    let day = parts.next().filter(|part| (1..=2).contains(&part.len()))?;
+    serial_from_date(digits(year)? as i64, digits(month)?, digits(day)?)
+}
+
+fn parse_time(raw: &str) -> Option<f64> {
+    let mut parts = raw.split(':');
+    let hour = parts.next().filter(|part| (1..=2).contains(&part.len()))?;
+    let minute = parts.next().filter(|part| part.len() == 2)?;
+    let second = match parts.next() {
+        Some(part) if part.len() == 2 => digits(part)?,
+        Some(_) => return None,
+        None => 0,
+    };
+    let (hour, minute) = (digits(hour)?, digits(minute)?);
+    if parts.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    Some(f64::from(hour * 3600 + minute * 60 + second) / SECONDS_PER_DAY)
+}
+
+fn digits(part: &str) -> Option<u32> {
+    if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    part.parse().ok()
+}
+
+/// Whole days and the time of day in seconds, rounded to the nearest
+/// second so `0.1 + 0.2`-style drift does not show up as `13:59:59`.
+fn split_serial(serial: f64) -> (i64, i64) {
+    let total = (serial * SECONDS_PER_DAY).round() as i64;
+    (total.div_euclid(86_400), total.rem_euclid(86_400))
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from the serial epoch to a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468 + UNIX_EPOCH_SERIAL
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days - UNIX_EPOCH_SERIAL + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Serial number of 1970-01-01.
+const UNIX_EPOCH_SERIAL: i64 = 25_569;
+
+/// Serials of 0001-01-01 and of the day after 9999-12-31.
+const MIN_SERIAL: f64 = -693_593.0;
+const MAX_SERIAL: f64 = 2_958_466.0;
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DateKind, MAX_SERIAL, MIN_SERIAL, civil_from_days, days_from_civil, end_of_month,
+        format_pattern, is_weekend, parse, serial_from_date, serial_from_parts,
+    };
+
+    #[test]
+    fn civil_dates_round_trip_through_days() {
+        let (first, last) = (MIN_SERIAL as i64, MAX_SERIAL as i64);
+        for days in (first..last).step_by(17).chain([first, last - 1]) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days, "{}", days);
+        }
+        assert_eq!(civil_from_days(first), (1, 1, 1));
+        assert_eq!(civil_from_days(last - 1), (9999, 12, 31));
+    }
+
+    #[test]
+    fn serials_match_spreadsheets() {
+        assert_eq!(serial_from_date(1899, 12, 30), Some(0.0));
+        assert_eq!(serial_from_date(1970, 1, 1), Some(25_569.0));
+        assert_eq!(serial_from_date(2024, 2, 29), Some(45_351.0));
+        assert_eq!(serial_from_date(2023, 2, 29), None);
+        assert_eq!(serial_from_date(1900, 2, 29), None);
+        assert_eq!(serial_from_date(2000, 2, 29), Some(36_585.0));
+    }
+
+    #[test]
+    fn date_parts_roll_over() {
+        let day = |year, month, day| serial_from_date(year, month, day).unwrap();
+        assert_eq!(serial_from_parts(2024, 13, 1), day(2025, 1, 1));
+        assert_eq!(serial_from_parts(2024, 0, 1), day(2023, 12, 1));
+        assert_eq!(serial_from_parts(2024, 3, 0), day(2024, 2, 29));
+    }
+
+    #[test]
+    fn end_of_month_lands_on_the_last_day() {
+        let day = |year, month, day| serial_from_date(year, month, day).unwrap();
+        assert_eq!(end_of_month(day(2024, 1, 31), 1), day(2024, 2, 29));
+        assert_eq!(end_of_month(day(2023, 1, 15), 1), day(2023, 2, 28));
+        assert_eq!(end_of_month(day(2024, 3, 10), 0), day(2024, 3, 31));
+        assert_eq!(end_of_month(day(2024, 3, 10), -3), day(2023, 12, 31));
+        assert_eq!(end_of_month(day(2024, 11, 1), 14), day(2026, 1, 31));
+    }
+
+    #[test]
+    fn weekdays_and_patterns() {
+        let tuesday = serial_from_date(2024, 3, 5).unwrap();
+        assert!(!is_weekend(tuesday));
+        assert!(is_weekend(tuesday + 4.0));
+        assert!(is_weekend(tuesday + 5.0));
+        assert_eq!(
+            format_pattern(tuesday + 0.5, "%a %e %b %y %H:%M %% %q"),
+            "Tue 5 Mar 24 12:00 % %q"
+        );
+    }
+
+    #[test]
+    fn parse_reads_dates_and_times() {
+        let tuesday = serial_from_date(2024, 3, 5).unwrap();
+        assert_eq!(parse("2024-03-05"), Some((tuesday, DateKind::Date)));
+        assert_eq!(parse("06:00"), Some((0.25, DateKind::Time)));
+        assert_eq!(
+            parse("2024-03-05T18:00:00"),
+            Some((tuesday + 0.75, DateKind::DateTime))
+        );
+        assert_eq!(parse("2024-02-30"), None);
+        assert_eq!(parse("24:00"), None);
+    }
+}
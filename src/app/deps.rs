@@ -0,0 +1,389 @@
+use std::collections::{HashMap, HashSet, VecDeque, hash_map::Entry};
+
+use super::{CellId, sheets::SheetId};
+
+/// A cell on a particular sheet, so the graph can follow references from
+/// one sheet into another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct SheetCell {
+    pub(crate) sheet: SheetId,
+    pub(crate) cell: CellId,
+}
+
+impl SheetCell {
+    pub(crate) const fn new(sheet: SheetId, cell: CellId) -> Self {
+        Self { sheet, cell }
+    }
+
+    /// Sheet by sheet, then row-major.
+    fn order(&self) -> (SheetId, usize, usize) {
+        (self.sheet, self.cell.row, self.cell.col)
+    }
+}
+
+/// Inclusive rectangle of cells read by a formula.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Area {
+    sheet: SheetId,
+    from: CellId,
+    to: CellId,
+}
+
+impl Area {
+    pub(crate) fn new(sheet: SheetId, from: CellId, to: CellId) -> Self {
+        Self { sheet, from, to }
+    }
+
+    fn is_single(&self) -> bool {
+        self.from == self.to
+    }
+
+    /// The top-left cell, which is the whole area for a single cell.
+    fn single(&self) -> SheetCell {
+        SheetCell::new(self.sheet, self.from)
+    }
+
+    fn contains(&self, node: SheetCell) -> bool {
+        let cell = node.cell;
+        node.sheet == self.sheet
+            && (self.from.row..=self.to.row).contains(&cell.row)
+            && (self.from.col..=self.to.col).contains(&cell.col)
+    }
+
+    /// The buckets a range is filed under: one per column for ranges at
+    /// least as tall as they are wide, one per row otherwise, so the common
+    /// `A1:A10000` takes a single entry.
+    fn buckets(&self) -> Vec<Bucket> {
+        let sheet = self.sheet;
+        if self.to.row - self.from.row >= self.to.col - self.from.col {
+            (self.from.col..=self.to.col)
+                .map(|col| Bucket::Column(sheet, col))
+                .collect()
+        } else {
+            (self.from.row..=self.to.row)
+                .map(|row| Bucket::Row(sheet, row))
+                .collect()
+        }
+    }
+}
+
+/// A column or row of a sheet, used to find the ranges covering a cell
+/// without scanning all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Bucket {
+    Column(SheetId, usize),
+    Row(SheetId, usize),
+}
+
+/// Tracks which formulas read which cells, across every sheet of the
+/// workbook, so an edit only recomputes the cells downstream of it.
+///
+/// Single-cell references are indexed by the referenced cell. Ranges are
+/// filed under the columns or rows they span, so `A1:A100000` costs one
+/// entry instead of a hundred thousand, and finding the readers of a cell
+/// only looks at the ranges crossing its column or row.
+#[derive(Debug, Default)]
+pub(crate) struct DependencyGraph {
+    precedents: HashMap<SheetCell, Vec<Area>>,
+    dependents: HashMap<SheetCell, HashSet<SheetCell>>,
+    range_readers: HashMap<Bucket, HashSet<SheetCell>>,
+}
+
+impl DependencyGraph {
+    pub(crate) fn clear(&mut self) {
+        self.precedents.clear();
+        self.dependents.clear();
+        self.range_readers.clear();
+    }
+
+    /// Replaces the set of areas `cell` reads. An empty list removes it.
+    pub(crate) fn set_precedents(&mut self, cell: SheetCell, areas: Vec<Area>) {
+        if let Some(old) = self.precedents.remove(&cell) {
+            for area in &old {
+                if area.is_single() {
+                    unlink(&mut self.dependents, area.single(), cell);
+                } else {
+                    for bucket in area.buckets() {
+                        unlink(&mut self.range_readers, bucket, cell);
+                    }
+                }
+            }
+        }
+
+        if areas.is_empty() {
+            return;
+        }
+        for area in &areas {
+            if area.is_single() {
+                self.dependents
+                    .entry(area.single())
+                    .or_default()
+                    .insert(cell);
+            } else {
+                for bucket in area.buckets() {
+                    self.range_readers.entry(bucket).or_default().insert(cell);
+                }
+            }
+        }
+        self.precedents.insert(cell, areas);
+    }
+
+    /// Formulas that read `cell` directly.
+    pub(crate) fn direct_dependents(&self, cell: SheetCell) -> HashSet<SheetCell> {
+        let mut found: HashSet<SheetCell> = self.dependents.get(&cell).cloned().unwrap_or_default();
+        let (sheet, CellId { row, col }) = (cell.sheet, cell.cell);
+        for bucket in [Bucket::Column(sheet, col), Bucket::Row(sheet, row)] {
+            for reader in self.range_readers.get(&bucket).into_iter().flatten() {
+                let reads = self.precedents[reader]
+                    .iter()
+                    .any(|area| !area.is_single() && area.contains(cell));
+                if reads {
+                    found.insert(*reader);
+                }
+            }
+        }
+        found
+    }
+
+    /// The changed cells plus everything that transitively reads them.
+    pub(crate) fn affected_by(&self, changed: &[SheetCell]) -> HashSet<SheetCell> {
+        let mut seen: HashSet<SheetCell> = changed.iter().copied().collect();
+        let mut queue: VecDeque<SheetCell> = changed.iter().copied().collect();
+        while let Some(cell) = queue.pop_front() {
+            for reader in self.direct_dependents(cell) {
+                if seen.insert(reader) {
+                    queue.push_back(reader);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Orders `cells` so every cell comes after the cells it reads. Cells
+    /// that could not be ordered because they sit on or behind a cycle are
+    /// returned separately.
+    ///
+    /// Cells that read nothing go first, so only the edges between formulas
+    /// need building; a column of sums over a column of values has none.
+    pub(crate) fn evaluation_order(
+        &self,
+        cells: &HashSet<SheetCell>,
+    ) -> (Vec<SheetCell>, Vec<SheetCell>) {
+        let (formulas, plain): (Vec<SheetCell>, Vec<SheetCell>) = cells
+            .iter()
+            .partition(|cell| self.precedents.contains_key(cell));
+        let mut pending: HashMap<SheetCell, usize> =
+            formulas.iter().map(|cell| (*cell, 0)).collect();
+        let mut edges: HashMap<SheetCell, Vec<SheetCell>> = HashMap::new();
+        for cell in &formulas {
+            for reader in self.direct_dependents(*cell) {
+                if let Some(count) = pending.get_mut(&reader) {
+                    *count += 1;
+                    edges.entry(*cell).or_default().push(reader);
+                }
+            }
+        }
+
+        let mut ready: VecDeque<SheetCell> = pending
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(cell, _)| *cell)
+            .collect();
+        let mut order = plain;
+        order.reserve(formulas.len());
+        while let Some(cell) = ready.pop_front() {
+            order.push(cell);
+            for reader in edges.get(&cell).into_iter().flatten() {
+                let count = pending.get_mut(reader).expect("reader is pending");
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(*reader);
+                }
+            }
+        }
+
+        let blocked = pending
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(cell, _)| cell)
+            .collect();
+        (order, blocked)
+    }
+
+    /// Finds the reference cycles among `cells`, each reported as a chain
+    /// that starts and ends on the same cell and follows the references, so
+    /// `A1 -> B1 -> A1` means A1 reads B1 and B1 reads A1.
+    ///
+    /// Uses an iterative Tarjan so long chains cannot overflow the stack.
+    pub(crate) fn cycles(&self, cells: &[SheetCell]) -> Vec<Vec<SheetCell>> {
+        let members: HashSet<SheetCell> = cells.iter().copied().collect();
+        let reads = |cell: SheetCell| -> Vec<SheetCell> {
+            let mut found = Vec::new();
+            for area in self.precedents.get(&cell).into_iter().flatten() {
+                if area.is_single() {
+                    let target = area.single();
+                    if members.contains(&target) && !found.contains(&target) {
+                        found.push(target);
+                    }
+                    continue;
+                }
+                for other in members.iter().filter(|other| area.contains(**other)) {
+                    if !found.contains(other) {
+                        found.push(*other);
+                    }
+                }
+            }
+            found.sort_by_key(SheetCell::order);
+            found
+        };
+
+        let mut sorted = cells.to_vec();
+        sorted.sort_by_key(SheetCell::order);
+
+        let mut index: HashMap<SheetCell, usize> = HashMap::new();
+        let mut low: HashMap<SheetCell, usize> = HashMap::new();
+        let mut on_stack: HashSet<SheetCell> = HashSet::new();
+        let mut stack: Vec<SheetCell> = Vec::new();
+        let mut components: Vec<Vec<SheetCell>> = Vec::new();
+        let mut next_index = 0;
+
+        for root in sorted {
+            if index.contains_key(&root) {
+                continue;
+            }
+            let mut work: Vec<(SheetCell, Vec<SheetCell>, usize)> = vec![(root, reads(root), 0)];
+            index.insert(root, next_index);
+            low.insert(root, next_index);
+            next_index += 1;
+            stack.push(root);
+            on_stack.insert(root);
+
+            while let Some((cell, targets, pos)) = work.last_mut() {
+                let cell = *cell;
+                if let Some(target) = targets.get(*pos).copied() {
+                    *pos += 1;
+                    if let Entry::Vacant(entry) = index.entry(target) {
+                        entry.insert(next_index);
+                        low.insert(target, next_index);
+                        next_index += 1;
+                        stack.push(target);
+                        on_stack.insert(target);
+                        work.push((target, reads(target), 0));
+                    } else if on_stack.contains(&target) {
+                        let lowest = low[&cell].min(index[&target]);
+                        low.insert(cell, lowest);
+                    }
+                    continue;
+                }
+
+                work.pop();
+                if let Some((parent, _, _)) = work.last() {
+                    let lowest = low[parent].min(low[&cell]);
+                    low.insert(*parent, lowest);
+                }
+                if low[&cell] == index[&cell] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack.remove(&member);
+                        component.push(member);
+                        if member == cell {
+                            break;
+                        }
+                    }
+                    let self_loop = component.len() == 1 && reads(cell).contains(&cell);
+                    if component.len() > 1 || self_loop {
+                        components.push(component);
+                    }
+                }
+            }
+        }
+
+        components
+            .into_iter()
+            .map(|component| {
+                let start = component
+                    .iter()
+                    .copied()
+                    .min_by_key(SheetCell::order)
+                    .expect("components are never empty");
+                let inside: HashSet<SheetCell> = component.into_iter().collect();
+                shortest_chain(start, |cell| {
+                    reads(cell)
+                        .into_iter()
+                        .filter(|other| inside.contains(other))
+                        .collect()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Removes `reader` from the set filed under `key`, dropping the set once
+/// it is empty.
+fn unlink<K: Eq + std::hash::Hash>(
+    index: &mut HashMap<K, HashSet<SheetCell>>,
+    key: K,
+    reader: SheetCell,
+) {
+    if let Entry::Occupied(mut entry) = index.entry(key) {
+        entry.get_mut().remove(&reader);
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+    }
+}
+
+/// Breadth-first search for the shortest path from `start` back to itself.
+fn shortest_chain(start: SheetCell, next: impl Fn(SheetCell) -> Vec<SheetCell>) -> Vec<SheetCell> {
+    let mut came_from: HashMap<SheetCell, SheetCell> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(cell) = queue.pop_front() {
+        for target in next(cell) {
+            if target == start {
+                let mut chain = vec![start, cell];
+                let mut current = cell;
+                while current != start {
+                    current = came_from[&current];
+                    chain.push(current);
+                }
+                chain.reverse();
+                return chain;
+            }
+            if let Entry::Vacant(entry) = came_from.entry(target) {
+                entry.insert(cell);
+                queue.push_back(target);
+            }
+        }
+    }
+    vec![start, start]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Area, Bucket, DependencyGraph, SheetCell};
+    use crate::app::{CellId, sheets::Sheet};
+
+    #[test]
+    fn ranges_are_filed_under_their_long_side() {
+        let sheet = Sheet::named("Sheet1").id;
+        let node = |row, col| SheetCell::new(sheet, CellId::new(row, col));
+        let mut graph = DependencyGraph::default();
+        let tall = Area::new(sheet, CellId::new(0, 0), CellId::new(99_999, 0));
+        let wide = Area::new(sheet, CellId::new(5, 0), CellId::new(5, 9_999));
+        graph.set_precedents(node(0, 1), vec![tall]);
+        graph.set_precedents(node(0, 2), vec![wide]);
+
+        assert_eq!(graph.range_readers.len(), 2);
+        assert!(graph.range_readers.contains_key(&Bucket::Column(sheet, 0)));
+        assert!(graph.range_readers.contains_key(&Bucket::Row(sheet, 5)));
+        assert_eq!(
+            graph.direct_dependents(node(5, 0)),
+            [node(0, 1), node(0, 2)].into_iter().collect()
+        );
+        assert!(graph.direct_dependents(node(6, 1)).is_empty());
+
+        graph.set_precedents(node(0, 1), Vec::new());
+        graph.set_precedents(node(0, 2), Vec::new());
+        assert!(graph.range_readers.is_empty());
+    }
+}
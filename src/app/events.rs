@@ -8,7 +8,7 @@ impl App {
         match event::read()? {
             Event::Key(key) if key.kind == KeyEventKind::Press => self.on_key_event(key),
             Event::Mouse(_) => {}
-            Event::Resize(_, _) => {}
+            Event::Resize(width, _) => self.resize_grid(width),
             _ => {}
         }
         Ok(())
@@ -0,0 +1,279 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    App, CellId, datetime,
+    formula::Axis,
+    value::{Value, format_number},
+    visual::Selection,
+};
+
+/// How a number is shown. Formats only change the display; the stored and
+/// computed values keep full precision.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum NumberFormat {
+    General,
+    Fixed {
+        decimals: usize,
+        thousands: bool,
+    },
+    Currency {
+        symbol: String,
+        decimals: usize,
+    },
+    Percent {
+        decimals: usize,
+    },
+    Scientific {
+        decimals: usize,
+    },
+    /// A date or time pattern, see `datetime::format_pattern`.
+    Date {
+        pattern: String,
+    },
+}
+
+impl NumberFormat {
+    /// Parses the arguments of `:format`, such as `fixed 2`, `thousands`,
+    /// `currency € 2`, `percent 1`, `sci 3`, `date %d/%m/%Y` or `general`.
+    pub(crate) fn parse(args: &[&str]) -> Result<Self, String> {
+        let (name, rest) = args
+            .split_first()
+            .ok_or("usage: format <kind> [decimals]")?;
+        let decimals = |rest: &[&str], default: usize| match rest.first() {
+            Some(arg) => arg
+                .parse::<usize>()
+                .ok()
+                .filter(|decimals| *decimals <= 15)
+                .ok_or_else(|| format!("invalid decimals: {}", arg)),
+            None => Ok(default),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "general" | "clear" => Ok(NumberFormat::General),
+            "fixed" => Ok(NumberFormat::Fixed {
+                decimals: decimals(rest, 2)?,
+                thousands: false,
+            }),
+            "thousands" => Ok(NumberFormat::Fixed {
+                decimals: decimals(rest, 0)?,
+                thousands: true,
+            }),
+            "currency" => {
+                let (symbol, rest) = match rest.split_first() {
+                    Some((symbol, rest)) if symbol.parse::<usize>().is_err() => {
+                        (symbol.to_string(), rest)
+                    }
+                    _ => ("$".to_string(), rest),
+                };
+                Ok(NumberFormat::Currency {
+                    symbol,
+                    decimals: decimals(rest, 2)?,
+                })
+            }
+            "percent" => Ok(NumberFormat::Percent {
+                decimals: decimals(rest, 0)?,
+            }),
+            "sci" | "scientific" => Ok(NumberFormat::Scientific {
+                decimals: decimals(rest, 2)?,
+            }),
+            "date" | "time" | "datetime" if !rest.is_empty() => Ok(NumberFormat::Date {
+                pattern: rest.join(" "),
+            }),
+            "date" => Ok(NumberFormat::Date {
+                pattern: "%Y-%m-%d".to_string(),
+            }),
+            "time" => Ok(NumberFormat::Date {
+                pattern: "%H:%M:%S".to_string(),
+            }),
+            "datetime" => Ok(NumberFormat::Date {
+                pattern: "%Y-%m-%d %H:%M".to_string(),
+            }),
+            other => Err(format!("unknown format: {}", other)),
+        }
+    }
+
+    pub(crate) fn apply(&self, number: f64) -> String {
+        match self {
+            NumberFormat::General => format_number(number),
+            NumberFormat::Fixed {
+                decimals,
+                thousands,
+            } => fixed(number, *decimals, *thousands),
+            NumberFormat::Currency { symbol, decimals } => {
+                let amount = fixed(number.abs(), *decimals, true);
+                let sign =
+                    if number < 0.0 && amount.bytes().any(|b| b.is_ascii_digit() && b != b'0') {
+                        "-"
+                    } else {
+                        ""
+                    };
+                format!("{}{}{}", sign, symbol, amount)
+            }
+            NumberFormat::Percent { decimals } => {
+                format!("{}%", fixed(number * 100.0, *decimals, false))
+            }
+            NumberFormat::Scientific { decimals } => scientific(number, *decimals),
+            NumberFormat::Date { pattern } if datetime::in_range(number) => {
+                datetime::format_pattern(number, pattern)
+            }
+            NumberFormat::Date { .. } => format_number(number),
+        }
+    }
+}
+
+fn fixed(number: f64, decimals: usize, thousands: bool) -> String {
+    let text = format!("{:.*}", decimals, number);
+    if !thousands {
+        return text;
+    }
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", text.as_str()),
+    };
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (digits, None),
+    };
+    let mut grouped = String::with_capacity(whole.len() + whole.len() / 3);
+    for (idx, digit) in whole.chars().enumerate() {
+        if idx > 0 && (whole.len() - idx) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    match fraction {
+        Some(fraction) => format!("{}{}.{}", sign, grouped, fraction),
+        None => format!("{}{}", sign, grouped),
+    }
+}
+
+/// Spreadsheet style exponent notation, `1.23E+04`.
+fn scientific(number: f64, decimals: usize) -> String {
+    let text = format!("{:.*e}", decimals, number);
+    let Some((mantissa, exponent)) = text.split_once('e') else {
+        return text;
+    };
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}E{}{:02}", mantissa, sign, exponent.abs())
+}
+
+impl App {
+    /// The format in effect for `cell`, the most recently applied one
+    /// covering it.
+    pub(super) fn number_format(&self, cell: CellId) -> Option<&NumberFormat> {
+        self.formats
+            .iter()
+            .rev()
+            .find(|(area, _)| area.contains(cell.row, cell.col))
+            .map(|(_, format)| format)
+            .filter(|format| **format != NumberFormat::General)
+    }
+
+    /// Shows a value the way its cell's number format asks for.
+    pub(super) fn format_value(&self, cell: CellId, value: &Value) -> String {
+        match (value, self.number_format(cell)) {
+            (Value::Number(number) | Value::Date(number, _), Some(format)) => format.apply(*number),
+            (value, _) => value.to_string(),
+        }
+    }
+
+    /// Applies `format` over `area`, forgetting older formats it hides
+    /// completely.
+    pub(super) fn set_number_format(&mut self, area: Selection, format: NumberFormat) {
+        self.modified = true;
+        self.formats.retain(|(old, _)| !covers(area, *old));
+        if format != NumberFormat::General
+            || self.formats.iter().any(|(old, _)| overlaps(area, *old))
+        {
+            self.formats.push((area, format));
+        }
+    }
+
+    pub(super) fn handle_format_command(&mut self, args: &[&str]) {
+        let format = match NumberFormat::parse(args) {
+            Ok(format) => format,
+            Err(err) => {
+                self.command_buffer = err;
+                return;
+            }
+        };
+        let area = self.command_selection.unwrap_or(Selection {
+            top: self.cursor.row,
+            left: self.cursor.col,
+            bottom: self.cursor.row,
+            right: self.cursor.col,
+        });
+        self.set_number_format(area, format);
+        self.command_buffer = "format applied".to_string();
+    }
+
+    /// Moves formats along with their cells when lines are inserted.
+    pub(super) fn insert_format_lines(&mut self, axis: Axis, at: usize, count: usize) {
+        for (area, _) in &mut self.formats {
+            let (first, last) = span(*area, axis);
+            let shift = |index: usize| match index {
+                index if index >= at => index.saturating_add(count),
+                index => index,
+            };
+            *area = with_span(*area, axis, shift(first), shift(last));
+        }
+    }
+
+    /// Shrinks or drops formats when the lines `at..at + count` go away.
+    pub(super) fn delete_format_lines(&mut self, axis: Axis, at: usize, count: usize) {
+        let end = at + count;
+        self.formats.retain_mut(|(area, _)| {
+            let (first, last) = span(*area, axis);
+            if first >= at && last < end {
+                return false;
+            }
+            let first = match first {
+                index if index < at => index,
+                index if index < end => at,
+                index => index - count,
+            };
+            let last = match last {
+                usize::MAX => usize::MAX,
+                index if index < at => index,
+                index if index < end => at - 1,
+                index => index - count,
+            };
+            *area = with_span(*area, axis, first, last);
+            true
+        });
+    }
+}
+
+fn covers(outer: Selection, inner: Selection) -> bool {
+    outer.top <= inner.top
+        && outer.left <= inner.left
+        && outer.bottom >= inner.bottom
+        && outer.right >= inner.right
+}
+
+fn overlaps(a: Selection, b: Selection) -> bool {
+    a.top <= b.bottom && b.top <= a.bottom && a.left <= b.right && b.left <= a.right
+}
+
+fn span(area: Selection, axis: Axis) -> (usize, usize) {
+    match axis {
+        Axis::Row => (area.top, area.bottom),
+        Axis::Column => (area.left, area.right),
+    }
+}
+
+fn with_span(area: Selection, axis: Axis, first: usize, last: usize) -> Selection {
+    match axis {
+        Axis::Row => Selection {
+            top: first,
+            bottom: last,
+            ..area
+        },
+        Axis::Column => Selection {
+            left: first,
+            right: last,
+            ..area
+        },
+    }
+}
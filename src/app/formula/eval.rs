@@ -0,0 +1,240 @@
+use crate::app::{
+    CellId,
+    datetime::{self, DateKind},
+    value::{CellError, Value},
+};
+
+use super::{BinaryOp, Expr, UnaryOp, functions};
+
+/// Gives the evaluator access to the values of other cells.
+pub(crate) trait CellSource {
+    fn cell_value(&self, cell: CellId) -> Value;
+
+    /// Values of the non-empty cells inside the inclusive rectangle, in
+    /// row-major order.
+    fn range_values(&self, from: CellId, to: CellId) -> Vec<Value>;
+
+    /// Like `range_values` on the sheet called `sheet`, or `#REF!` if there
+    /// is no such sheet.
+    fn sheet_range_values(
+        &self,
+        sheet: &str,
+        from: CellId,
+        to: CellId,
+    ) -> Result<Vec<Value>, CellError>;
+
+    /// Value of a cell on the sheet called `sheet`.
+    fn sheet_cell_value(&self, sheet: &str, cell: CellId) -> Value {
+        match self.sheet_range_values(sheet, cell, cell) {
+            Ok(values) => values.into_iter().next().unwrap_or(Value::Empty),
+            Err(err) => Value::Error(err),
+        }
+    }
+}
+
+/// A `CellSource` over fixed values, shared by the formula tests.
+#[cfg(test)]
+pub(super) mod fixture {
+    use std::collections::HashMap;
+
+    use super::{CellSource, evaluate};
+    use crate::app::{
+        CellId,
+        formula::{parse, parse_cell_label},
+        value::{CellError, Value},
+    };
+
+    /// Cells given by label on a single sheet named `Sheet1`.
+    pub(crate) struct Cells(HashMap<CellId, Value>);
+
+    impl Cells {
+        pub(crate) fn new(cells: &[(&str, Value)]) -> Self {
+            Self(
+                cells
+                    .iter()
+                    .map(|(label, value)| (parse_cell_label(label).unwrap(), value.clone()))
+                    .collect(),
+            )
+        }
+
+        /// Evaluates a formula body such as `SUM(A1:A3)`.
+        pub(crate) fn eval(&self, body: &str) -> Value {
+            match parse(body) {
+                Ok(expr) => evaluate(&expr, self),
+                Err(err) => Value::Error(err),
+            }
+        }
+    }
+
+    impl CellSource for Cells {
+        fn cell_value(&self, cell: CellId) -> Value {
+            self.0.get(&cell).cloned().unwrap_or(Value::Empty)
+        }
+
+        fn range_values(&self, from: CellId, to: CellId) -> Vec<Value> {
+            let mut cells: Vec<_> = self
+                .0
+                .iter()
+                .filter(|(cell, _)| {
+                    (from.row..=to.row).contains(&cell.row)
+                        && (from.col..=to.col).contains(&cell.col)
+                })
+                .collect();
+            cells.sort_by_key(|(cell, _)| (cell.row, cell.col));
+            cells.into_iter().map(|(_, value)| value.clone()).collect()
+        }
+
+        fn sheet_range_values(
+            &self,
+            sheet: &str,
+            from: CellId,
+            to: CellId,
+        ) -> Result<Vec<Value>, CellError> {
+            match sheet.eq_ignore_ascii_case("Sheet1") {
+                true => Ok(self.range_values(from, to)),
+                false => Err(CellError::Ref),
+            }
+        }
+    }
+}
+
+pub(crate) fn evaluate(expr: &Expr, source: &impl CellSource) -> Value {
+    match expr {
+        Expr::Number(number) => Value::Number(*number),
+        Expr::Text(text) => Value::Text(text.clone()),
+        Expr::Bool(flag) => Value::Bool(*flag),
+        Expr::Error(err) => Value::Error(*err),
+        Expr::Ref(cell) => source.cell_value(*cell),
+        Expr::Range(_, _) => Value::Error(CellError::Value),
+        Expr::External(sheet, from, to) if from == to => source.sheet_cell_value(sheet, *from),
+        Expr::External(_, _, _) => Value::Error(CellError::Value),
+        Expr::Call(name, args) => functions::call(name, args, source),
+        Expr::Unary(op, operand) => {
+            let operand = match evaluate(operand, source).as_number() {
+                Ok(number) => number,
+                Err(err) => return Value::Error(err),
+            };
+            match op {
+                UnaryOp::Neg => Value::Number(-operand),
+                UnaryOp::Plus => Value::Number(operand),
+            }
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = evaluate(lhs, source);
+            let rhs = evaluate(rhs, source);
+            match binary(*op, &lhs, &rhs) {
+                Ok(value) => value,
+                Err(err) => Value::Error(err),
+            }
+        }
+    }
+}
+
+fn binary(op: BinaryOp, lhs: &Value, rhs: &Value) -> Result<Value, CellError> {
+    if op == BinaryOp::Concat {
+        let mut text = lhs.as_text()?;
+        text.push_str(&rhs.as_text()?);
+        return Ok(Value::Text(text));
+    }
+
+    let kind = date_kind(op, lhs, rhs);
+    let lhs = lhs.as_number()?;
+    let rhs = rhs.as_number()?;
+    let result = match op {
+        BinaryOp::Add => lhs + rhs,
+        BinaryOp::Sub => lhs - rhs,
+        BinaryOp::Mul => lhs * rhs,
+        BinaryOp::Div if rhs == 0.0 => return Err(CellError::Div0),
+        BinaryOp::Div => lhs / rhs,
+        BinaryOp::Pow if lhs == 0.0 && rhs < 0.0 => return Err(CellError::Div0),
+        BinaryOp::Pow => lhs.powf(rhs),
+        BinaryOp::Concat => unreachable!("handled above"),
+    };
+
+    match kind {
+        _ if !result.is_finite() => Err(CellError::Num),
+        Some(kind) if datetime::in_range(result) => Ok(Value::Date(result, kind)),
+        Some(_) => Err(CellError::Num),
+        None => Ok(Value::Number(result)),
+    }
+}
+
+/// Moving a date by a number of days gives a date again, and adding a time
+/// to a date gives a date and time, while the difference between two dates
+/// is a plain number of days.
+fn date_kind(op: BinaryOp, lhs: &Value, rhs: &Value) -> Option<DateKind> {
+    match (op, lhs, rhs) {
+        (BinaryOp::Add, Value::Date(_, a), Value::Date(_, b)) => match (a, b) {
+            (DateKind::Date, DateKind::Date) => None,
+            (DateKind::Time, DateKind::Time) => Some(DateKind::Time),
+            _ => Some(DateKind::DateTime),
+        },
+        (BinaryOp::Sub, Value::Date(_, _), Value::Date(_, _)) => None,
+        (BinaryOp::Add | BinaryOp::Sub, Value::Date(_, kind), _)
+        | (BinaryOp::Add, _, Value::Date(_, kind)) => Some(*kind),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::Cells;
+    use crate::app::value::{CellError, Value};
+
+    fn eval(body: &str) -> Value {
+        Cells::new(&[]).eval(body)
+    }
+
+    #[test]
+    fn unary_minus_binds_tighter_than_power() {
+        assert_eq!(eval("-2^2"), Value::Number(4.0));
+        assert_eq!(eval("0-2^2"), Value::Number(-4.0));
+        assert_eq!(eval("2^3^2"), Value::Number(64.0));
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence() {
+        assert_eq!(eval("1+2*3"), Value::Number(7.0));
+        assert_eq!(eval("(1+2)*3"), Value::Number(9.0));
+        assert_eq!(eval("10-4-3"), Value::Number(3.0));
+        assert_eq!(eval("12/2/3"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn concatenation_binds_looser_than_addition() {
+        assert_eq!(eval("1+2&3"), Value::Text("33".to_string()));
+        assert_eq!(eval("\"a\"&1*2"), Value::Text("a2".to_string()));
+        assert_eq!(
+            eval("\"say \"\"hi\"\"\""),
+            Value::Text("say \"hi\"".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_have_their_codes() {
+        assert_eq!(eval("1/0"), Value::Error(CellError::Div0));
+        assert_eq!(eval("0^-1"), Value::Error(CellError::Div0));
+        assert_eq!(eval("\"a\"+1"), Value::Error(CellError::Value));
+        assert_eq!(eval("A1:A2"), Value::Error(CellError::Value));
+        assert_eq!(eval("Missing!A1"), Value::Error(CellError::Ref));
+        assert_eq!(eval("#REF!+1"), Value::Error(CellError::Ref));
+        assert_eq!(eval("NOPE(1)"), Value::Error(CellError::Name));
+        assert_eq!(eval("1+"), Value::Error(CellError::Parse));
+    }
+
+    #[test]
+    fn errors_spread_through_operators() {
+        let cells = Cells::new(&[("A1", Value::Error(CellError::Div0))]);
+        assert_eq!(cells.eval("A1*2"), Value::Error(CellError::Div0));
+        assert_eq!(cells.eval("A1&\"x\""), Value::Error(CellError::Div0));
+        assert_eq!(cells.eval("-A1"), Value::Error(CellError::Div0));
+    }
+
+    #[test]
+    fn references_read_cells() {
+        let cells = Cells::new(&[("A1", Value::Number(2.0)), ("B2", Value::Number(5.0))]);
+        assert_eq!(cells.eval("A1*B2"), Value::Number(10.0));
+        assert_eq!(cells.eval("$A$1+Sheet1!B2"), Value::Number(7.0));
+        assert_eq!(cells.eval("C3+1"), Value::Number(1.0));
+    }
+}
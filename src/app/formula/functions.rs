@@ -0,0 +1,330 @@
+use crate::app::{
+    datetime::{self, DateKind},
+    value::{CellError, Value},
+};
+
+use super::{CellSource, Expr, evaluate};
+
+pub(crate) fn call(name: &str, args: &[Expr], source: &impl CellSource) -> Value {
+    let args = collect(args, source);
+    let result = match name {
+        "SUM" => sum(&args),
+        "AVERAGE" => average(&args),
+        "MIN" => min(&args),
+        "MAX" => max(&args),
+        "COUNT" => count(&args),
+        "COUNTA" => counta(&args),
+        "TODAY" => today(&args),
+        "NOW" => now(&args),
+        "DATE" => date(&args),
+        "YEAR" => date_part(&args, |(year, _, _)| year as f64),
+        "MONTH" => date_part(&args, |(_, month, _)| f64::from(month)),
+        "DAY" => date_part(&args, |(_, _, day)| f64::from(day)),
+        "EOMONTH" => eomonth(&args),
+        "NETWORKDAYS" => networkdays(&args),
+        _ => Err(CellError::Name),
+    };
+    result.unwrap_or_else(Value::Error)
+}
+
+/// An evaluated function argument. Values that came through a reference are
+/// kept apart from literals because aggregates skip text and booleans found
+/// in cells but try to coerce those passed directly, the way spreadsheets do.
+enum Arg {
+    Scalar(Value),
+    Reference(Vec<Value>),
+}
+
+fn collect(args: &[Expr], source: &impl CellSource) -> Vec<Arg> {
+    args.iter()
+        .map(|arg| match arg {
+            Expr::Ref(cell) => Arg::Reference(vec![source.cell_value(*cell)]),
+            Expr::Range(from, to) => Arg::Reference(source.range_values(*from, *to)),
+            Expr::External(sheet, from, to) => match source.sheet_range_values(sheet, *from, *to) {
+                Ok(values) => Arg::Reference(values),
+                Err(err) => Arg::Scalar(Value::Error(err)),
+            },
+            other => Arg::Scalar(evaluate(other, source)),
+        })
+        .collect()
+}
+
+fn numbers(args: &[Arg]) -> Result<Vec<f64>, CellError> {
+    if args.is_empty() {
+        return Err(CellError::Value);
+    }
+    let mut numbers = Vec::new();
+    for arg in args {
+        match arg {
+            Arg::Scalar(value) => numbers.push(value.as_number()?),
+            Arg::Reference(values) => {
+                for value in values {
+                    match value {
+                        Value::Number(number) | Value::Date(number, _) => numbers.push(*number),
+                        Value::Error(err) => return Err(*err),
+                        Value::Empty | Value::Text(_) | Value::Bool(_) => {}
+                    }
+                }
+            }
+        }
+    }
+    Ok(numbers)
+}
+
+fn sum(args: &[Arg]) -> Result<Value, CellError> {
+    Ok(Value::Number(numbers(args)?.iter().sum()))
+}
+
+fn average(args: &[Arg]) -> Result<Value, CellError> {
+    let numbers = numbers(args)?;
+    if numbers.is_empty() {
+        return Err(CellError::Div0);
+    }
+    Ok(Value::Number(
+        numbers.iter().sum::<f64>() / numbers.len() as f64,
+    ))
+}
+
+fn min(args: &[Arg]) -> Result<Value, CellError> {
+    let numbers = numbers(args)?;
+    let min = numbers.iter().copied().reduce(f64::min).unwrap_or(0.0);
+    Ok(Value::Number(min))
+}
+
+fn max(args: &[Arg]) -> Result<Value, CellError> {
+    let numbers = numbers(args)?;
+    let max = numbers.iter().copied().reduce(f64::max).unwrap_or(0.0);
+    Ok(Value::Number(max))
+}
+
+fn count(args: &[Arg]) -> Result<Value, CellError> {
+    let mut count = 0;
+    for arg in args {
+        match arg {
+            Arg::Scalar(value) => count += usize::from(value.as_number().is_ok()),
+            Arg::Reference(values) => {
+                count += values.iter().filter(|value| value.is_numeric()).count();
+            }
+        }
+    }
+    Ok(Value::Number(count as f64))
+}
+
+fn counta(args: &[Arg]) -> Result<Value, CellError> {
+    let mut count = 0;
+    for arg in args {
+        match arg {
+            Arg::Scalar(_) => count += 1,
+            Arg::Reference(values) => {
+                count += values
+                    .iter()
+                    .filter(|value| **value != Value::Empty)
+                    .count();
+            }
+        }
+    }
+    Ok(Value::Number(count as f64))
+}
+
+fn arity(args: &[Arg], min: usize, max: usize) -> Result<(), CellError> {
+    if (min..=max).contains(&args.len()) {
+        Ok(())
+    } else {
+        Err(CellError::Value)
+    }
+}
+
+/// Reads one number from an argument. Text that looks like a date, such
+/// as `"2024-03-05"`, counts as that date.
+fn scalar(arg: &Arg) -> Result<f64, CellError> {
+    let value = match arg {
+        Arg::Scalar(value) => value,
+        Arg::Reference(values) => match values.as_slice() {
+            [] => return Ok(0.0),
+            [value] => value,
+            _ => return Err(CellError::Value),
+        },
+    };
+    value.as_number().or_else(|err| match value {
+        Value::Text(text) => datetime::parse(text).map(|(serial, _)| serial).ok_or(err),
+        _ => Err(err),
+    })
+}
+
+fn date_value(serial: f64, kind: DateKind) -> Result<Value, CellError> {
+    if datetime::in_range(serial) {
+        Ok(Value::Date(serial, kind))
+    } else {
+        Err(CellError::Num)
+    }
+}
+
+fn date_arg(arg: &Arg) -> Result<f64, CellError> {
+    let serial = scalar(arg)?;
+    if datetime::in_range(serial) {
+        Ok(serial)
+    } else {
+        Err(CellError::Num)
+    }
+}
+
+fn today(args: &[Arg]) -> Result<Value, CellError> {
+    arity(args, 0, 0)?;
+    date_value(datetime::now().floor(), DateKind::Date)
+}
+
+fn now(args: &[Arg]) -> Result<Value, CellError> {
+    arity(args, 0, 0)?;
+    date_value(datetime::now(), DateKind::DateTime)
+}
+
+fn date(args: &[Arg]) -> Result<Value, CellError> {
+    arity(args, 3, 3)?;
+    let year = scalar(&args[0])?.trunc();
+    let month = scalar(&args[1])?.trunc();
+    let day = scalar(&args[2])?.trunc();
+    if !(0.0..=9999.0).contains(&year) || month.abs() > 120_000.0 || day.abs() > 3_700_000.0 {
+        return Err(CellError::Num);
+    }
+    let serial = datetime::serial_from_parts(year as i64, month as i64, day as i64);
+    date_value(serial, DateKind::Date)
+}
+
+fn date_part(args: &[Arg], part: impl Fn((i64, u32, u32)) -> f64) -> Result<Value, CellError> {
+    arity(args, 1, 1)?;
+    let serial = date_arg(&args[0])?;
+    Ok(Value::Number(part(datetime::date_parts(serial))))
+}
+
+fn eomonth(args: &[Arg]) -> Result<Value, CellError> {
+    arity(args, 2, 2)?;
+    let start = date_arg(&args[0])?;
+    let months = scalar(&args[1])?.trunc();
+    if months.abs() > 120_000.0 {
+        return Err(CellError::Num);
+    }
+    date_value(datetime::end_of_month(start, months as i64), DateKind::Date)
+}
+
+/// Weekdays from start to end inclusive, minus any holidays that fall on
+/// them. Negative when end comes before start.
+fn networkdays(args: &[Arg]) -> Result<Value, CellError> {
+    arity(args, 2, 3)?;
+    let start = date_arg(&args[0])?.floor();
+    let end = date_arg(&args[1])?.floor();
+    let mut holidays = match args.get(2) {
+        Some(arg) => numbers(std::slice::from_ref(arg))?,
+        None => Vec::new(),
+    };
+    holidays.iter_mut().for_each(|day| *day = day.floor());
+    holidays.sort_by(f64::total_cmp);
+    holidays.dedup();
+
+    let (first, last) = (start.min(end), start.max(end));
+    let days = (last - first) as i64 + 1;
+    let mut count = days / 7 * 5;
+    let mut day = first + (days / 7 * 7) as f64;
+    while day <= last {
+        count += i64::from(!datetime::is_weekend(day));
+        day += 1.0;
+    }
+    count -= holidays
+        .iter()
+        .filter(|day| (first..=last).contains(*day) && !datetime::is_weekend(**day))
+        .count() as i64;
+
+    let count = if start > end { -count } else { count };
+    Ok(Value::Number(count as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        datetime::{self, DateKind},
+        value::{CellError, Value},
+    };
+
+    use super::super::eval::fixture::Cells;
+
+    fn column() -> Cells {
+        Cells::new(&[
+            ("A1", Value::Number(1.0)),
+            ("A2", Value::Text("note".to_string())),
+            ("A3", Value::Number(5.0)),
+            ("A4", Value::Bool(true)),
+        ])
+    }
+
+    #[test]
+    fn aggregates_skip_text_in_ranges() {
+        let cells = column();
+        assert_eq!(cells.eval("SUM(A1:A4)"), Value::Number(6.0));
+        assert_eq!(cells.eval("AVERAGE(A1:A4)"), Value::Number(3.0));
+        assert_eq!(cells.eval("MIN(A1:A4)"), Value::Number(1.0));
+        assert_eq!(cells.eval("MAX(A1:A4)"), Value::Number(5.0));
+        assert_eq!(cells.eval("COUNT(A1:A4)"), Value::Number(2.0));
+        assert_eq!(cells.eval("COUNTA(A1:A9)"), Value::Number(4.0));
+    }
+
+    #[test]
+    fn aggregates_coerce_literals() {
+        let cells = Cells::new(&[]);
+        assert_eq!(cells.eval("SUM(1,\"2\",TRUE)"), Value::Number(4.0));
+        assert_eq!(cells.eval("SUM(1,\"x\")"), Value::Error(CellError::Value));
+        assert_eq!(cells.eval("sum(2,3)"), Value::Number(5.0));
+    }
+
+    #[test]
+    fn functions_report_errors() {
+        let cells = Cells::new(&[("B1", Value::Error(CellError::Ref))]);
+        assert_eq!(cells.eval("AVERAGE(C1:C3)"), Value::Error(CellError::Div0));
+        assert_eq!(cells.eval("SUM(B1:B2)"), Value::Error(CellError::Ref));
+        assert_eq!(cells.eval("SUM()"), Value::Error(CellError::Value));
+        assert_eq!(cells.eval("TODAY(1)"), Value::Error(CellError::Value));
+        assert_eq!(cells.eval("DATE(2024,1)"), Value::Error(CellError::Value));
+    }
+
+    #[test]
+    fn eomonth_returns_month_ends() {
+        let cells = Cells::new(&[]);
+        let day = |year, month, day| {
+            Value::Date(
+                datetime::serial_from_date(year, month, day).unwrap(),
+                DateKind::Date,
+            )
+        };
+        assert_eq!(cells.eval("EOMONTH(\"2024-01-31\",1)"), day(2024, 2, 29));
+        assert_eq!(cells.eval("EOMONTH(DATE(2024,5,20),-5)"), day(2023, 12, 31));
+        assert_eq!(
+            cells.eval("EOMONTH(\"soon\",1)"),
+            Value::Error(CellError::Value)
+        );
+    }
+
+    #[test]
+    fn networkdays_counts_weekdays_without_holidays() {
+        let holiday = datetime::serial_from_date(2024, 3, 8).unwrap();
+        let cells = Cells::new(&[
+            ("A1", Value::Date(holiday, DateKind::Date)),
+            ("A2", Value::Date(holiday + 1.0, DateKind::Date)),
+        ]);
+        // Monday 2024-03-04 to Sunday 2024-03-17 holds ten weekdays.
+        let span = "\"2024-03-04\",\"2024-03-17\"";
+        assert_eq!(
+            cells.eval(&format!("NETWORKDAYS({})", span)),
+            Value::Number(10.0)
+        );
+        assert_eq!(
+            cells.eval(&format!("NETWORKDAYS({},A1:A2)", span)),
+            Value::Number(9.0)
+        );
+        assert_eq!(
+            cells.eval("NETWORKDAYS(\"2024-03-17\",\"2024-03-04\")"),
+            Value::Number(-10.0)
+        );
+        assert_eq!(
+            cells.eval("NETWORKDAYS(\"2024-03-09\",\"2024-03-10\")"),
+            Value::Number(0.0)
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use std::ops::Range;
+
+use crate::app::{CellId, render::column_index, value::CellError};
+
+use super::CellRef;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TokenKind {
+    Number(f64),
+    Text(String),
+    Ref(CellRef),
+    /// A `Summary!` or `'Q1 Totals'!` prefix naming the sheet of the
+    /// reference that follows.
+    Sheet(String),
+    Error(CellError),
+    Ident(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    Ampersand,
+    LParen,
+    RParen,
+    Colon,
+    Comma,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Token {
+    pub(crate) kind: TokenKind,
+    pub(crate) span: Range<usize>,
+}
+
+pub(crate) fn tokenize(src: &str) -> Result<Vec<Token>, CellError> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let start = pos;
+        let c = bytes[pos];
+        let kind = match c {
+            b' ' | b'\t' => {
+                pos += 1;
+                continue;
+            }
+            b'+' => single(&mut pos, TokenKind::Plus),
+            b'-' => single(&mut pos, TokenKind::Minus),
+            b'*' => single(&mut pos, TokenKind::Star),
+            b'/' => single(&mut pos, TokenKind::Slash),
+            b'^' => single(&mut pos, TokenKind::Caret),
+            b'&' => single(&mut pos, TokenKind::Ampersand),
+            b'(' => single(&mut pos, TokenKind::LParen),
+            b')' => single(&mut pos, TokenKind::RParen),
+            b':' => single(&mut pos, TokenKind::Colon),
+            b',' => single(&mut pos, TokenKind::Comma),
+            b'"' => lex_text(src, &mut pos)?,
+            b'#' => lex_error(src, &mut pos)?,
+            b'\'' => lex_quoted_sheet(src, &mut pos)?,
+            b'0'..=b'9' | b'.' => lex_number(src, &mut pos)?,
+            c if c.is_ascii_alphabetic() || c == b'_' || c == b'$' => lex_word(src, &mut pos),
+            _ => return Err(CellError::Parse),
+        };
+        tokens.push(Token {
+            kind,
+            span: start..pos,
+        });
+    }
+
+    Ok(tokens)
+}
+
+fn single(pos: &mut usize, kind: TokenKind) -> TokenKind {
+    *pos += 1;
+    kind
+}
+
+/// Strings use spreadsheet quoting: `""` inside a literal is one quote.
+fn lex_text(src: &str, pos: &mut usize) -> Result<TokenKind, CellError> {
+    let bytes = src.as_bytes();
+    let mut text = String::new();
+    let mut segment_start = *pos + 1;
+    let mut idx = segment_start;
+
+    while idx < bytes.len() {
+        if bytes[idx] == b'"' {
+            text.push_str(&src[segment_start..idx]);
+            if bytes.get(idx + 1) == Some(&b'"') {
+                text.push('"');
+                idx += 2;
+                segment_start = idx;
+                continue;
+            }
+            *pos = idx + 1;
+            return Ok(TokenKind::Text(text));
+        }
+        idx += 1;
+    }
+
+    Err(CellError::Parse)
+}
+
+/// Error codes such as `#REF!` may appear literally, for example after a
+/// referenced row has been deleted.
+fn lex_error(src: &str, pos: &mut usize) -> Result<TokenKind, CellError> {
+    let rest = &src[*pos..];
+    let err = CellError::ALL
+        .into_iter()
+        .find(|err| {
+            let code = err.to_string();
+            rest.get(..code.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&code))
+        })
+        .ok_or(CellError::Parse)?;
+    *pos += err.to_string().len();
+    Ok(TokenKind::Error(err))
+}
+
+/// Sheet names that are not plain words are quoted, `'Q1 Totals'!A1`,
+/// with `''` standing for a quote inside the name.
+fn lex_quoted_sheet(src: &str, pos: &mut usize) -> Result<TokenKind, CellError> {
+    let bytes = src.as_bytes();
+    let mut name = String::new();
+    let mut segment_start = *pos + 1;
+    let mut idx = segment_start;
+
+    while idx < bytes.len() {
+        if bytes[idx] == b'\'' {
+            name.push_str(&src[segment_start..idx]);
+            if bytes.get(idx + 1) == Some(&b'\'') {
+                name.push('\'');
+                idx += 2;
+                segment_start = idx;
+                continue;
+            }
+            if bytes.get(idx + 1) != Some(&b'!') {
+                return Err(CellError::Parse);
+            }
+            *pos = idx + 2;
+            return Ok(TokenKind::Sheet(name));
+        }
+        idx += 1;
+    }
+
+    Err(CellError::Parse)
+}
+
+fn lex_number(src: &str, pos: &mut usize) -> Result<TokenKind, CellError> {
+    let bytes = src.as_bytes();
+    let start = *pos;
+    let mut idx = start;
+
+    while idx < bytes.len() && (bytes[idx].is_ascii_digit() || bytes[idx] == b'.') {
+        idx += 1;
+    }
+    if idx < bytes.len() && matches!(bytes[idx], b'e' | b'E') {
+        let mut exp = idx + 1;
+        if exp < bytes.len() && matches!(bytes[exp], b'+' | b'-') {
+            exp += 1;
+        }
+        if exp < bytes.len() && bytes[exp].is_ascii_digit() {
+            while exp < bytes.len() && bytes[exp].is_ascii_digit() {
+                exp += 1;
+            }
+            idx = exp;
+        }
+    }
+
+    let number = src[start..idx]
+        .parse::<f64>()
+        .map_err(|_| CellError::Parse)?;
+    *pos = idx;
+    Ok(TokenKind::Number(number))
+}
+
+fn lex_word(src: &str, pos: &mut usize) -> TokenKind {
+    let bytes = src.as_bytes();
+    let start = *pos;
+    let mut idx = start;
+
+    while idx < bytes.len()
+        && (bytes[idx].is_ascii_alphanumeric() || matches!(bytes[idx], b'_' | b'.' | b'$'))
+    {
+        idx += 1;
+    }
+    *pos = idx;
+
+    let word = &src[start..idx];
+    if bytes.get(idx) == Some(&b'!') {
+        *pos = idx + 1;
+        return TokenKind::Sheet(word.to_string());
+    }
+    let is_call = src[idx..].trim_start().starts_with('(');
+    match parse_cell_ref(word) {
+        Some(reference) if !is_call => TokenKind::Ref(reference),
+        _ => TokenKind::Ident(word.to_ascii_uppercase()),
+    }
+}
+
+/// Parses `B3`-style labels, with columns going up to three letters (`XFD`)
+/// and an optional `$` anchor before the column and before the row.
+pub(crate) fn parse_cell_ref(word: &str) -> Option<CellRef> {
+    let (col_fixed, word) = strip_anchor(word);
+    let letters = word.bytes().take_while(|b| b.is_ascii_alphabetic()).count();
+    let (col, row) = word.split_at(letters);
+    let (row_fixed, row) = strip_anchor(row);
+    if letters == 0 || letters > 3 || row.is_empty() || !row.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let row = row.parse::<usize>().ok()?.checked_sub(1)?;
+    let col = column_index(&col.to_ascii_uppercase());
+    Some(CellRef {
+        cell: CellId::new(row, col),
+        col_fixed,
+        row_fixed,
+    })
+}
+
+fn strip_anchor(part: &str) -> (bool, &str) {
+    match part.strip_prefix('$') {
+        Some(rest) => (true, rest),
+        None => (false, part),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TokenKind, parse_cell_ref, tokenize};
+    use crate::app::{CellId, value::CellError};
+
+    fn kinds(src: &str) -> Vec<TokenKind> {
+        tokenize(src)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    #[test]
+    fn cell_refs_keep_their_anchors() {
+        let reference = parse_cell_ref("$B3").unwrap();
+        assert_eq!(reference.cell, CellId::new(2, 1));
+        assert!(reference.col_fixed && !reference.row_fixed);
+        let reference = parse_cell_ref("aa$10").unwrap();
+        assert_eq!(reference.cell, CellId::new(9, 26));
+        assert!(!reference.col_fixed && reference.row_fixed);
+        assert!(parse_cell_ref("A0").is_none());
+        assert!(parse_cell_ref("ABCD1").is_none());
+    }
+
+    #[test]
+    fn text_and_errors_are_single_tokens() {
+        assert_eq!(
+            kinds("\"a\"\"b\" & #div/0!"),
+            vec![
+                TokenKind::Text("a\"b".to_string()),
+                TokenKind::Ampersand,
+                TokenKind::Error(CellError::Div0),
+            ]
+        );
+        assert_eq!(tokenize("\"open"), Err(CellError::Parse));
+        assert_eq!(tokenize("#WHAT"), Err(CellError::Parse));
+    }
+
+    #[test]
+    fn sheet_prefixes_come_before_refs() {
+        assert_eq!(
+            kinds("'Q1 ''24'!A1"),
+            vec![
+                TokenKind::Sheet("Q1 '24".to_string()),
+                TokenKind::Ref(parse_cell_ref("A1").unwrap()),
+            ]
+        );
+        assert_eq!(kinds("sum(")[0], TokenKind::Ident("SUM".to_string()));
+    }
+}
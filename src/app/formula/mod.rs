@@ -0,0 +1,153 @@
+mod eval;
+mod functions;
+mod lexer;
+mod parser;
+mod rewrite;
+
+use std::fmt;
+
+use super::{CellId, render::column_name, value::CellError};
+
+pub(crate) use eval::{CellSource, evaluate};
+pub(crate) use rewrite::{
+    Axis, cycle_anchor, delete_lines, drop_sheet, insert_lines, rename_sheet, sheet_prefix,
+    translate,
+};
+
+/// A cell reference as written in a formula. A `$` before the column or
+/// row pins that part when the formula is copied, pasted or filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CellRef {
+    pub(crate) cell: CellId,
+    pub(crate) col_fixed: bool,
+    pub(crate) row_fixed: bool,
+}
+
+impl CellRef {
+    /// The same anchors pointing at another cell.
+    pub(crate) const fn at(self, cell: CellId) -> Self {
+        Self { cell, ..self }
+    }
+
+    /// Steps through `A1`, `$A$1`, `A$1`, `$A1` and back, like F4.
+    pub(crate) const fn next_anchor(self) -> Self {
+        let (col_fixed, row_fixed) = match (self.col_fixed, self.row_fixed) {
+            (false, false) => (true, true),
+            (true, true) => (false, true),
+            (false, true) => (true, false),
+            (true, false) => (false, false),
+        };
+        Self {
+            cell: self.cell,
+            col_fixed,
+            row_fixed,
+        }
+    }
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let anchor = |fixed: bool| if fixed { "$" } else { "" };
+        write!(
+            f,
+            "{}{}{}{}",
+            anchor(self.col_fixed),
+            column_name(self.cell.col),
+            anchor(self.row_fixed),
+            self.cell.row + 1
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Error(CellError),
+    Ref(CellId),
+    /// Inclusive rectangle stored as top-left and bottom-right corners.
+    Range(CellId, CellId),
+    /// A cell or range on a named sheet, corners stored like `Range`.
+    External(String, CellId, CellId),
+    Call(String, Vec<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn range(a: CellId, b: CellId) -> Self {
+        let (from, to) = corners(a, b);
+        Expr::Range(from, to)
+    }
+
+    fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Self {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    /// Every cell or range the expression reads, as inclusive rectangles,
+    /// with the sheet named by the reference or `None` for the formula's
+    /// own sheet.
+    pub(crate) fn references(&self) -> Vec<(Option<&str>, CellId, CellId)> {
+        let mut found = Vec::new();
+        self.visit(&mut |expr| match expr {
+            Expr::Ref(cell) => found.push((None, *cell, *cell)),
+            Expr::Range(from, to) => found.push((None, *from, *to)),
+            Expr::External(name, from, to) => found.push((Some(name.as_str()), *from, *to)),
+            _ => {}
+        });
+        found
+    }
+
+    fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Expr)) {
+        f(self);
+        match self {
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.visit(f)),
+            Expr::Unary(_, operand) => operand.visit(f),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.visit(f);
+                rhs.visit(f);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Top-left and bottom-right corners of the rectangle spanned by two cells.
+fn corners(a: CellId, b: CellId) -> (CellId, CellId) {
+    (
+        CellId::new(a.row.min(b.row), a.col.min(b.col)),
+        CellId::new(a.row.max(b.row), a.col.max(b.col)),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UnaryOp {
+    Neg,
+    Plus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Concat,
+}
+
+/// Returns the expression part of a cell value if it is a formula.
+pub(crate) fn formula_body(raw: &str) -> Option<&str> {
+    raw.strip_prefix('=')
+}
+
+/// Reads a plain cell label such as `B3`.
+pub(crate) fn parse_cell_label(label: &str) -> Option<CellId> {
+    lexer::parse_cell_ref(label).map(|reference| reference.cell)
+}
+
+pub(crate) fn parse(src: &str) -> Result<Expr, CellError> {
+    let tokens = lexer::tokenize(src)?;
+    parser::Parser::new(&tokens).parse()
+}
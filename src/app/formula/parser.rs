@@ -0,0 +1,237 @@
+use crate::app::value::CellError;
+
+use super::{
+    BinaryOp, Expr, UnaryOp, corners,
+    lexer::{Token, TokenKind},
+};
+
+/// Recursive descent over the token stream, loosest binding first:
+/// `&`, then `+ -`, then `* /`, then `^`, then unary signs.
+pub(crate) struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(tokens: &'a [Token]) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    pub(crate) fn parse(mut self) -> Result<Expr, CellError> {
+        if self.tokens.is_empty() {
+            return Err(CellError::Parse);
+        }
+        let expr = self.concat()?;
+        if self.pos != self.tokens.len() {
+            return Err(CellError::Parse);
+        }
+        Ok(expr)
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|token| &token.kind)
+    }
+
+    fn next(&mut self) -> Option<&TokenKind> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(&token.kind)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek() == Some(kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr, CellError> {
+        let mut lhs = self.additive()?;
+        while self.eat(&TokenKind::Ampersand) {
+            let rhs = self.additive()?;
+            lhs = Expr::binary(BinaryOp::Concat, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn additive(&mut self) -> Result<Expr, CellError> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(TokenKind::Plus) => BinaryOp::Add,
+                Some(TokenKind::Minus) => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.multiplicative()?;
+            lhs = Expr::binary(op, lhs, rhs);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, CellError> {
+        let mut lhs = self.power()?;
+        loop {
+            let op = match self.peek() {
+                Some(TokenKind::Star) => BinaryOp::Mul,
+                Some(TokenKind::Slash) => BinaryOp::Div,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.power()?;
+            lhs = Expr::binary(op, lhs, rhs);
+        }
+    }
+
+    /// `^` is left associative and binds looser than a leading minus,
+    /// matching spreadsheets where `-2^2` is 4.
+    fn power(&mut self) -> Result<Expr, CellError> {
+        let mut lhs = self.unary()?;
+        while self.eat(&TokenKind::Caret) {
+            let rhs = self.unary()?;
+            lhs = Expr::binary(BinaryOp::Pow, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CellError> {
+        if self.eat(&TokenKind::Minus) {
+            let operand = self.unary()?;
+            return Ok(Expr::Unary(UnaryOp::Neg, Box::new(operand)));
+        }
+        if self.eat(&TokenKind::Plus) {
+            let operand = self.unary()?;
+            return Ok(Expr::Unary(UnaryOp::Plus, Box::new(operand)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, CellError> {
+        match self.next().cloned() {
+            Some(TokenKind::Number(number)) => Ok(Expr::Number(number)),
+            Some(TokenKind::Text(text)) => Ok(Expr::Text(text)),
+            Some(TokenKind::Error(err)) => Ok(Expr::Error(err)),
+            Some(TokenKind::Ref(start)) => {
+                if !self.eat(&TokenKind::Colon) {
+                    return Ok(Expr::Ref(start.cell));
+                }
+                match self.next() {
+                    Some(TokenKind::Ref(end)) => Ok(Expr::range(start.cell, end.cell)),
+                    _ => Err(CellError::Parse),
+                }
+            }
+            Some(TokenKind::Sheet(sheet)) => {
+                let Some(TokenKind::Ref(start)) = self.next().cloned() else {
+                    return Err(CellError::Parse);
+                };
+                if !self.eat(&TokenKind::Colon) {
+                    return Ok(Expr::External(sheet, start.cell, start.cell));
+                }
+                match self.next() {
+                    Some(TokenKind::Ref(end)) => {
+                        let (from, to) = corners(start.cell, end.cell);
+                        Ok(Expr::External(sheet, from, to))
+                    }
+                    _ => Err(CellError::Parse),
+                }
+            }
+            Some(TokenKind::Ident(name)) => {
+                if !self.eat(&TokenKind::LParen) {
+                    return match name.as_str() {
+                        "TRUE" => Ok(Expr::Bool(true)),
+                        "FALSE" => Ok(Expr::Bool(false)),
+                        _ => Err(CellError::Name),
+                    };
+                }
+                let args = self.arguments()?;
+                Ok(Expr::Call(name, args))
+            }
+            Some(TokenKind::LParen) => {
+                let inner = self.concat()?;
+                if !self.eat(&TokenKind::RParen) {
+                    return Err(CellError::Parse);
+                }
+                Ok(inner)
+            }
+            _ => Err(CellError::Parse),
+        }
+    }
+
+    /// Parses a comma separated argument list, the opening paren having
+    /// already been consumed.
+    fn arguments(&mut self) -> Result<Vec<Expr>, CellError> {
+        let mut args = Vec::new();
+        if self.eat(&TokenKind::RParen) {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.concat()?);
+            match self.next() {
+                Some(TokenKind::Comma) => {}
+                Some(TokenKind::RParen) => return Ok(args),
+                _ => return Err(CellError::Parse),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        CellId,
+        formula::{BinaryOp, Expr, UnaryOp, parse},
+        value::CellError,
+    };
+
+    fn number(value: f64) -> Box<Expr> {
+        Box::new(Expr::Number(value))
+    }
+
+    #[test]
+    fn negation_applies_before_power() {
+        assert_eq!(
+            parse("-2^2"),
+            Ok(Expr::Binary(
+                BinaryOp::Pow,
+                Box::new(Expr::Unary(UnaryOp::Neg, number(2.0))),
+                number(2.0),
+            ))
+        );
+    }
+
+    #[test]
+    fn concatenation_is_the_outermost_operator() {
+        assert_eq!(
+            parse("1+2&3"),
+            Ok(Expr::Binary(
+                BinaryOp::Concat,
+                Box::new(Expr::Binary(BinaryOp::Add, number(1.0), number(2.0))),
+                number(3.0),
+            ))
+        );
+    }
+
+    #[test]
+    fn ranges_are_stored_by_their_corners() {
+        assert_eq!(
+            parse("B3:A1"),
+            Ok(Expr::Range(CellId::new(0, 0), CellId::new(2, 1)))
+        );
+        assert_eq!(
+            parse("Data!A2:A1"),
+            Ok(Expr::External(
+                "Data".to_string(),
+                CellId::new(0, 0),
+                CellId::new(1, 0),
+            ))
+        );
+    }
+
+    #[test]
+    fn malformed_formulas_fail_to_parse() {
+        for body in ["", "1+", "(1", "1)", "SUM(1,", "A1:"] {
+            assert_eq!(parse(body), Err(CellError::Parse), "{:?}", body);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use crate::app::CellId;
+
+use super::{
+    CellRef, formula_body,
+    lexer::{TokenKind, tokenize},
+};
+
+/// A reference as written in a formula, before any evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reference {
+    Cell(CellRef),
+    Range(CellRef, CellRef),
+}
+
+/// Rewrites every reference in a formula body through `map`, which also
+/// gets the sheet the reference names, if any. The rest of the text is left
+/// untouched. References mapped to `None` become `#REF!`, sheet prefix and
+/// all. Bodies that do not tokenize are returned unchanged.
+fn rewrite_references(
+    body: &str,
+    mut map: impl FnMut(Option<&str>, Reference) -> Option<Reference>,
+) -> String {
+    let Ok(tokens) = tokenize(body) else {
+        return body.to_string();
+    };
+
+    let mut out = String::with_capacity(body.len());
+    let mut copied = 0;
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let TokenKind::Ref(start) = tokens[idx].kind else {
+            idx += 1;
+            continue;
+        };
+        let sheet = match idx.checked_sub(1).map(|prev| &tokens[prev]) {
+            Some(token) => match &token.kind {
+                TokenKind::Sheet(name) => Some((name.as_str(), token.span.start)),
+                _ => None,
+            },
+            None => None,
+        };
+        let range_end = match (tokens.get(idx + 1), tokens.get(idx + 2)) {
+            (Some(colon), Some(end)) if colon.kind == TokenKind::Colon => match end.kind {
+                TokenKind::Ref(end_cell) => Some((end_cell, end.span.end)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let (reference, span_end, consumed) = match range_end {
+            Some((end, span_end)) => (Reference::Range(start, end), span_end, 3),
+            None => (Reference::Cell(start), tokens[idx].span.end, 1),
+        };
+
+        match map(sheet.map(|(name, _)| name), reference) {
+            Some(reference) => {
+                out.push_str(&body[copied..tokens[idx].span.start]);
+                out.push_str(&reference_label(reference));
+            }
+            None => {
+                let start = sheet.map_or(tokens[idx].span.start, |(_, start)| start);
+                out.push_str(&body[copied..start]);
+                out.push_str("#REF!");
+            }
+        }
+        copied = span_end;
+        idx += consumed;
+    }
+    out.push_str(&body[copied..]);
+    out
+}
+
+/// Moves every reference in a cell value by the same offset, as when a
+/// formula is copied from one cell to another. Anchored columns and rows
+/// stay put. Plain values pass through.
+pub(crate) fn translate(raw: &str, delta_row: isize, delta_col: isize) -> String {
+    let shift = |reference: CellRef| -> Option<CellRef> {
+        let CellId { row, col } = reference.cell;
+        let row = match reference.row_fixed {
+            true => row,
+            false => row.checked_add_signed(delta_row)?,
+        };
+        let col = match reference.col_fixed {
+            true => col,
+            false => col.checked_add_signed(delta_col)?,
+        };
+        Some(reference.at(CellId::new(row, col)))
+    };
+    rewrite_formula(raw, |_, reference| match reference {
+        Reference::Cell(cell) => shift(cell).map(Reference::Cell),
+        Reference::Range(from, to) => Some(Reference::Range(shift(from)?, shift(to)?)),
+    })
+}
+
+/// Points references naming sheet `old` at `new` instead.
+pub(crate) fn rename_sheet(raw: &str, old: &str, new: &str) -> String {
+    let Some(body) = formula_body(raw) else {
+        return raw.to_string();
+    };
+    let Ok(tokens) = tokenize(body) else {
+        return raw.to_string();
+    };
+
+    let mut out = String::from("=");
+    let mut copied = 0;
+    for token in &tokens {
+        if let TokenKind::Sheet(name) = &token.kind
+            && name.eq_ignore_ascii_case(old)
+        {
+            out.push_str(&body[copied..token.span.start]);
+            out.push_str(&sheet_prefix(new));
+            copied = token.span.end;
+        }
+    }
+    out.push_str(&body[copied..]);
+    out
+}
+
+/// Turns every reference to sheet `name` into `#REF!`, for when the sheet
+/// is deleted.
+pub(crate) fn drop_sheet(raw: &str, name: &str) -> String {
+    rewrite_formula(raw, |sheet, reference| match sheet {
+        Some(sheet) if sheet.eq_ignore_ascii_case(name) => None,
+        _ => Some(reference),
+    })
+}
+
+/// How a sheet is named in a reference: `Summary!`, or quoted as
+/// `'Q1 Totals'!` when the name is not a plain word.
+pub(crate) fn sheet_prefix(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.'));
+    if plain {
+        format!("{}!", name)
+    } else {
+        format!("'{}'!", name.replace('\'', "''"))
+    }
+}
+
+/// Cycles the anchors of the reference touching byte offset `cursor` of a
+/// formula being edited. Returns the new text and the offset just past the
+/// rewritten reference, or `None` when the cursor is not on a reference.
+pub(crate) fn cycle_anchor(raw: &str, cursor: usize) -> Option<(String, usize)> {
+    let body = formula_body(raw)?;
+    let offset = raw.len() - body.len();
+    let at = cursor.checked_sub(offset)?;
+    let tokens = tokenize(body).ok()?;
+    let (span, reference) = tokens.iter().find_map(|token| match token.kind {
+        TokenKind::Ref(reference) if token.span.start <= at && at <= token.span.end => {
+            Some((token.span.clone(), reference))
+        }
+        _ => None,
+    })?;
+
+    let label = reference.next_anchor().to_string();
+    let end = offset + span.start + label.len();
+    let text = format!(
+        "{}{}{}",
+        &raw[..offset + span.start],
+        label,
+        &raw[offset + span.end..]
+    );
+    Some((text, end))
+}
+
+fn reference_label(reference: Reference) -> String {
+    match reference {
+        Reference::Cell(cell) => cell.to_string(),
+        Reference::Range(from, to) => format!("{}:{}", from, to),
+    }
+}
+
+/// Which coordinate a structural edit shifts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Axis {
+    Row,
+    Column,
+}
+
+impl Axis {
+    pub(crate) fn of(&self, cell: CellId) -> usize {
+        match self {
+            Axis::Row => cell.row,
+            Axis::Column => cell.col,
+        }
+    }
+
+    pub(crate) fn with(&self, cell: CellId, index: usize) -> CellId {
+        match self {
+            Axis::Row => CellId::new(index, cell.col),
+            Axis::Column => CellId::new(cell.row, index),
+        }
+    }
+}
+
+/// Moves references after `count` rows or columns were inserted before
+/// index `at` of the sheet picked out by `on_sheet`, which is given the
+/// sheet a reference names, if any. Ranges spanning the insertion point
+/// grow to include them.
+pub(crate) fn insert_lines(
+    raw: &str,
+    axis: Axis,
+    at: usize,
+    count: usize,
+    on_sheet: impl Fn(Option<&str>) -> bool,
+) -> String {
+    let shift = |reference: CellRef| match axis.of(reference.cell) {
+        index if index >= at => reference.at(axis.with(reference.cell, index + count)),
+        _ => reference,
+    };
+    rewrite_formula(raw, |sheet, reference| match reference {
+        _ if !on_sheet(sheet) => Some(reference),
+        Reference::Cell(cell) => Some(Reference::Cell(shift(cell))),
+        Reference::Range(from, to) => Some(Reference::Range(shift(from), shift(to))),
+    })
+}
+
+/// Moves references after the rows or columns `at..at + count` were
+/// deleted from the sheet picked out by `on_sheet`. References into the
+/// deleted lines become `#REF!`, while ranges that only partly overlap
+/// them shrink.
+pub(crate) fn delete_lines(
+    raw: &str,
+    axis: Axis,
+    at: usize,
+    count: usize,
+    on_sheet: impl Fn(Option<&str>) -> bool,
+) -> String {
+    let end = at + count;
+    rewrite_formula(raw, |sheet, reference| match reference {
+        _ if !on_sheet(sheet) => Some(reference),
+        Reference::Cell(cell) => match axis.of(cell.cell) {
+            index if index < at => Some(Reference::Cell(cell)),
+            index if index < end => None,
+            index => Some(Reference::Cell(
+                cell.at(axis.with(cell.cell, index - count)),
+            )),
+        },
+        Reference::Range(from, to) => {
+            // Ranges may be written bottom-right first, as in `A5:A1`.
+            let reversed = axis.of(from.cell) > axis.of(to.cell);
+            let (low, high) = if reversed { (to, from) } else { (from, to) };
+            let (first, last) = (axis.of(low.cell), axis.of(high.cell));
+            if first >= at && last < end {
+                return None;
+            }
+            let first = match first {
+                index if index < at => index,
+                index if index < end => at,
+                index => index - count,
+            };
+            let last = match last {
+                index if index < at => index,
+                index if index < end => at - 1,
+                index => index - count,
+            };
+            let low = low.at(axis.with(low.cell, first));
+            let high = high.at(axis.with(high.cell, last));
+            Some(match reversed {
+                true => Reference::Range(high, low),
+                false => Reference::Range(low, high),
+            })
+        }
+    })
+}
+
+fn rewrite_formula(
+    raw: &str,
+    map: impl FnMut(Option<&str>, Reference) -> Option<Reference>,
+) -> String {
+    match formula_body(raw) {
+        Some(body) => format!("={}", rewrite_references(body, map)),
+        None => raw.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Axis, delete_lines, insert_lines, rename_sheet, sheet_prefix, translate};
+
+    fn delete_rows(raw: &str, at: usize, count: usize) -> String {
+        delete_lines(raw, Axis::Row, at, count, |sheet| sheet.is_none())
+    }
+
+    #[test]
+    fn deleting_rows_shrinks_ranges_in_either_order() {
+        assert_eq!(delete_rows("=SUM(A1:A5)", 0, 1), "=SUM(A1:A4)");
+        assert_eq!(delete_rows("=SUM(A5:A1)", 0, 1), "=SUM(A4:A1)");
+        assert_eq!(delete_rows("=SUM(A5:A2)", 1, 2), "=SUM(A3:A2)");
+        assert_eq!(delete_rows("=SUM($A$5:B2)", 1, 2), "=SUM($A$3:B2)");
+        assert_eq!(delete_rows("=SUM(A3:A2)", 1, 2), "=SUM(#REF!)");
+    }
+
+    #[test]
+    fn deleting_rows_moves_and_drops_cells() {
+        assert_eq!(delete_rows("=A1+A3+A5", 1, 2), "=A1+#REF!+A3");
+        assert_eq!(delete_rows("=Other!A5+A5", 0, 1), "=Other!A5+A4");
+    }
+
+    #[test]
+    fn inserting_columns_grows_spanning_ranges() {
+        let insert = |raw| insert_lines(raw, Axis::Column, 1, 2, |sheet| sheet.is_none());
+        assert_eq!(insert("=SUM(A1:C1)"), "=SUM(A1:E1)");
+        assert_eq!(insert("=SUM(C1:A1)"), "=SUM(E1:A1)");
+    }
+
+    #[test]
+    fn translating_keeps_anchors() {
+        assert_eq!(translate("=A1+$B$2+C$3", 1, 1), "=B2+$B$2+D$3");
+        assert_eq!(translate("=A1", -1, 0), "=#REF!");
+        assert_eq!(translate("plain", 3, 3), "plain");
+    }
+
+    #[test]
+    fn sheet_names_are_quoted_when_needed() {
+        assert_eq!(sheet_prefix("Summary"), "Summary!");
+        assert_eq!(sheet_prefix("Q1 Totals"), "'Q1 Totals'!");
+        assert_eq!(sheet_prefix("it's"), "'it''s'!");
+        assert_eq!(
+            rename_sheet("=Old!A1+old!B2", "old", "New One"),
+            "='New One'!A1+'New One'!B2"
+        );
+    }
+}
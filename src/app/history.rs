@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use super::{CellId, Cursor, format::NumberFormat, sheets::SheetId, visual::Selection};
+
+/// One cell going from `before` to `after`; `None` means empty.
+#[derive(Debug, Clone)]
+pub(crate) struct CellChange {
+    pub(crate) cell: CellId,
+    pub(crate) before: Option<String>,
+    pub(crate) after: Option<String>,
+}
+
+/// What a sheet keeps about its rows and columns besides their cells,
+/// which moves with them when lines are inserted or deleted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Layout {
+    pub(crate) column_widths: HashMap<usize, u16>,
+    pub(crate) formats: Vec<(Selection, NumberFormat)>,
+}
+
+/// A single undo step: every cell touched by one user action, plus where
+/// the cursor was when the action started. Structural edits also keep the
+/// layout from before and after them, and the formulas they rewrote on
+/// other sheets.
+#[derive(Debug, Clone)]
+pub(crate) struct Revision {
+    pub(crate) changes: Vec<CellChange>,
+    pub(crate) cursor: Cursor,
+    pub(crate) layout: Option<(Layout, Layout)>,
+    pub(crate) elsewhere: Vec<(SheetId, Vec<CellChange>)>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    undo: Vec<Revision>,
+    redo: Vec<Revision>,
+    pending: Option<Revision>,
+}
+
+impl History {
+    /// Opens a group that collects changes until `commit`. Returns false if
+    /// a group was already open, in which case the caller's changes join it
+    /// and the caller must not commit.
+    pub(crate) fn begin(&mut self, cursor: Cursor) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        self.pending = Some(Revision {
+            changes: Vec::new(),
+            cursor,
+            layout: None,
+            elsewhere: Vec::new(),
+        });
+        true
+    }
+
+    pub(crate) fn record(&mut self, cell: CellId, before: Option<String>, after: Option<String>) {
+        let Some(revision) = self.pending.as_mut() else {
+            return;
+        };
+        match revision
+            .changes
+            .iter_mut()
+            .find(|change| change.cell == cell)
+        {
+            Some(change) => change.after = after,
+            None => revision.changes.push(CellChange {
+                cell,
+                before,
+                after,
+            }),
+        }
+    }
+
+    /// Records the layout going from `before` to `after`. Like cells, only
+    /// the first `before` of a group is kept.
+    pub(crate) fn record_layout(&mut self, before: Layout, after: Layout) {
+        let Some(revision) = self.pending.as_mut() else {
+            return;
+        };
+        match &mut revision.layout {
+            Some((_, last)) => *last = after,
+            None => revision.layout = Some((before, after)),
+        }
+    }
+
+    /// Records cells of another sheet that changed along with this one.
+    pub(crate) fn record_elsewhere(&mut self, sheet: SheetId, changes: Vec<CellChange>) {
+        let Some(revision) = self.pending.as_mut() else {
+            return;
+        };
+        if !changes.is_empty() {
+            revision.elsewhere.push((sheet, changes));
+        }
+    }
+
+    pub(crate) fn commit(&mut self) {
+        let Some(mut revision) = self.pending.take() else {
+            return;
+        };
+        revision
+            .changes
+            .retain(|change| change.before != change.after);
+        revision.layout.take_if(|(before, after)| before == after);
+        let changed = !revision.changes.is_empty() || !revision.elsewhere.is_empty();
+        if changed || revision.layout.is_some() {
+            self.undo.push(revision);
+            self.redo.clear();
+        }
+    }
+
+    pub(crate) fn undo(&mut self) -> Option<Revision> {
+        let revision = self.undo.pop()?;
+        self.redo.push(revision.clone());
+        Some(revision)
+    }
+
+    pub(crate) fn redo(&mut self) -> Option<Revision> {
+        let revision = self.redo.pop()?;
+        self.undo.push(revision.clone());
+        Some(revision)
+    }
+
+    /// Passes every value kept for undo and redo through `rewrite`, so the
+    /// history follows a sheet being renamed or deleted.
+    pub(crate) fn rewrite(&mut self, rewrite: impl Fn(&str) -> String) {
+        for revision in self.undo.iter_mut().chain(&mut self.redo) {
+            let elsewhere = revision
+                .elsewhere
+                .iter_mut()
+                .flat_map(|(_, changes)| changes);
+            for change in revision.changes.iter_mut().chain(elsewhere) {
+                for raw in [&mut change.before, &mut change.after]
+                    .into_iter()
+                    .flatten()
+                {
+                    *raw = rewrite(raw);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.pending = None;
+    }
+}
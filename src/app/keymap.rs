@@ -1,17 +1,29 @@
 use std::{
-    cmp::Ordering,
-    fs::File,
+    collections::HashMap,
+    fs::{self, File},
     io::{self, Write},
 };
 
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
-use crate::app::Cursor;
+use crate::app::{Cursor, Viewport};
 
-use super::{App, CellId, InsertState, Mode};
+use super::{
+    App, CellId, InsertState, Mode, VisualKind,
+    clipboard::{ClipboardBackend, ClipboardKind, paste_writes},
+    csv::{csv_escape, parse_csv},
+    formula,
+    motion::Motion,
+    native,
+    pending::{Operator, Pending, Prefix},
+    repeat::{Change, Edit, InsertStart, VisualAction},
+    search::{SearchDirection, SearchScope},
+    sheets::Sheet,
+};
 
 impl App {
     pub(crate) fn on_key_event(&mut self, key: KeyEvent) {
+        self.record_key(key);
         if key.modifiers.contains(KeyModifiers::CONTROL) {
             match key.code {
                 KeyCode::Char('s') => {
@@ -34,125 +46,293 @@ impl App {
             Mode::Normal => self.handle_normal_mode(key),
             Mode::Insert(_) => self.handle_insert_mode(key),
             Mode::Command => self.handle_command_mode(key),
+            Mode::Search(direction) => self.handle_search_mode(key, direction),
+            Mode::Confirm => self.handle_confirm_mode(key),
+            Mode::Visual(_) => self.handle_visual_mode(key),
         }
     }
 
     fn handle_normal_mode(&mut self, key: KeyEvent) {
+        if let KeyCode::Char(digit) = key.code
+            && key.modifiers.is_empty()
+            && self.pending.takes_digit(digit)
+        {
+            self.pending.push_digit(digit);
+            return;
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        if let Some(prefix) = pending.prefix() {
+            self.handle_prefixed_key(pending, prefix, key);
+            return;
+        }
+        if let Some(operator) = pending.operator() {
+            self.handle_operator_key(pending, operator, key);
+            return;
+        }
+        if let Some(motion) = Motion::from_key(key) {
+            self.clear_command_buffer();
+            self.run_motion(pending, motion);
+            return;
+        }
+
+        let count = pending.count();
         match key.code {
-            KeyCode::Char('b') => {
-                self.clear_command_buffer();
-                self.move_cursor(-5, 0);
+            KeyCode::Char('z') if key.modifiers.is_empty() => {
+                self.pending = pending.with_prefix(Prefix::Column);
             }
-            KeyCode::Char('w') => {
+            KeyCode::Char('>') => {
                 self.clear_command_buffer();
-                self.move_cursor(5, 0);
+                self.resize_current_column(clamp_delta(count));
             }
-            KeyCode::Left | KeyCode::Char('h') => {
+            KeyCode::Char('<') => {
                 self.clear_command_buffer();
-                self.move_cursor(-1, 0);
+                self.resize_current_column(-clamp_delta(count));
             }
-            KeyCode::Right | KeyCode::Char('l') => {
-                self.clear_command_buffer();
-                self.move_cursor(1, 0);
+            KeyCode::Char('g') if key.modifiers.is_empty() => {
+                self.pending = pending.with_prefix(Prefix::Go);
             }
-            KeyCode::Up | KeyCode::Char('k') => {
+            KeyCode::Char('o') if key.modifiers.is_empty() => {
                 self.clear_command_buffer();
-                self.move_cursor(0, -1);
+                self.begin_insert(InsertStart::RowBelow);
             }
-            KeyCode::Down | KeyCode::Char('j') => {
+            KeyCode::Char('O') => {
                 self.clear_command_buffer();
-                self.move_cursor(0, 1);
+                self.begin_insert(InsertStart::RowAbove);
             }
-            KeyCode::Char('g') if key.modifiers.is_empty() => match self.command_buffer.as_str() {
-                "g" => {
-                    self.go_to_first_row();
-                    self.clear_command_buffer();
-                }
-                _ => {
-                    self.command_buffer.clear();
-                    self.command_buffer.push('g');
-                }
+            KeyCode::Char('d') if key.modifiers.is_empty() => {
+                self.pending = pending.with_operator(Operator::Delete);
+            }
+            KeyCode::Char('y') if key.modifiers.is_empty() => {
+                self.pending = pending.with_operator(Operator::Yank);
+            }
+            KeyCode::Char('c') if key.modifiers.is_empty() => {
+                self.pending = pending.with_operator(Operator::Change);
+            }
+            KeyCode::Char('"') => {
+                self.pending = pending.with_prefix(Prefix::Register);
+            }
+            KeyCode::Char('q') if key.modifiers.is_empty() => match self.macros.recording() {
+                Some(_) => self.stop_recording(),
+                None => self.pending = pending.with_prefix(Prefix::Record),
             },
-            KeyCode::Char('G') => {
-                self.command_buffer.clear();
-                self.go_to_last_row_with_value();
+            KeyCode::Char('@') => {
+                self.pending = pending.with_prefix(Prefix::Macro);
             }
-            KeyCode::Char('o') if key.modifiers.is_empty() => {
+            KeyCode::Char('p' | 'P')
+                if key.modifiers.is_empty() && pending.register() == Some('+') =>
+            {
                 self.clear_command_buffer();
-                self.insert_row_below_and_edit();
+                self.paste_from_system_clipboard();
             }
-            KeyCode::Char('O') => {
+            KeyCode::Char('p') if key.modifiers.is_empty() => {
                 self.clear_command_buffer();
-                self.insert_row_above_and_edit();
+                self.perform(Change::Paste { after: true, count });
             }
-            KeyCode::Char('d') if key.modifiers.is_empty() => match self.command_buffer.as_str() {
-                "d" => {
-                    self.delete_current_row();
-                    self.clear_command_buffer();
-                }
-                _ => {
-                    self.command_buffer.clear();
-                    self.command_buffer.push('d');
-                }
-            },
-            KeyCode::Char('y') if key.modifiers.is_empty() => match self.command_buffer.as_str() {
-                "y" => {
-                    self.copy_current_cell_to_clipboard();
-                    self.clear_command_buffer();
-                }
-                _ => {
-                    self.command_buffer.clear();
-                    self.command_buffer.push('y');
+            KeyCode::Char('P') => {
+                self.clear_command_buffer();
+                self.perform(Change::Paste {
+                    after: false,
+                    count,
+                });
+            }
+            KeyCode::Char('.') => {
+                self.clear_command_buffer();
+                self.repeat_last_change(pending.has_count().then_some(count));
+            }
+            KeyCode::Char('u') if key.modifiers.is_empty() => {
+                self.clear_command_buffer();
+                for _ in 0..count {
+                    self.undo();
                 }
-            },
-            KeyCode::Char('p') if key.modifiers.is_empty() => {
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 self.clear_command_buffer();
-                self.paste_clipboard_into_cell();
+                for _ in 0..count {
+                    self.redo();
+                }
+            }
+            KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.enter_visual_mode(VisualKind::Column);
+            }
+            KeyCode::Char('v') if key.modifiers.is_empty() => {
+                self.enter_visual_mode(VisualKind::Cell);
+            }
+            KeyCode::Char('V') => {
+                self.enter_visual_mode(VisualKind::Row);
             }
             KeyCode::Char('i') if key.modifiers.is_empty() => {
                 self.clear_command_buffer();
-                self.enter_insert_mode_at_start();
+                self.begin_insert(InsertStart::Start);
             }
             KeyCode::Char(':') if key.modifiers.is_empty() => {
                 self.clear_command_buffer();
                 self.enter_command_mode();
             }
+            KeyCode::Char('/') => self.enter_search_mode(SearchDirection::Forward),
+            KeyCode::Char('?') => self.enter_search_mode(SearchDirection::Backward),
+            KeyCode::Char('n') if key.modifiers.is_empty() => {
+                for _ in 0..count {
+                    self.search_next(false);
+                }
+            }
+            KeyCode::Char('N') => {
+                for _ in 0..count {
+                    self.search_next(true);
+                }
+            }
             KeyCode::Char('a') if key.modifiers.is_empty() => {
                 self.clear_command_buffer();
-                self.enter_insert_mode_at_end();
+                self.begin_insert(InsertStart::End);
             }
             KeyCode::Esc => {
                 self.clear_command_buffer();
             }
-            KeyCode::Enter => {
-                self.move_cursor(0, 1);
-            }
             _ => {
                 self.clear_command_buffer();
             }
         }
     }
 
-    fn handle_insert_mode(&mut self, key: KeyEvent) {
+    /// Finishes a two-key command such as `gg`, `zh` or `"+p`.
+    fn handle_prefixed_key(&mut self, pending: Pending, prefix: Prefix, key: KeyEvent) {
+        let count = pending.count();
+        self.clear_command_buffer();
+        match (prefix, key.code) {
+            (Prefix::Go, KeyCode::Char('g')) => self.run_motion(pending, Motion::FirstRow),
+            (Prefix::Go, KeyCode::Char('t')) => match pending.has_count() {
+                true => self.switch_sheet(count - 1),
+                false => self.cycle_sheet(1),
+            },
+            (Prefix::Go, KeyCode::Char('T')) => self.cycle_sheet(-(count as isize)),
+            (Prefix::Column, KeyCode::Char('h')) => self.perform(Change::InsertColumns {
+                right: false,
+                count,
+            }),
+            (Prefix::Column, KeyCode::Char('l')) => {
+                self.perform(Change::InsertColumns { right: true, count })
+            }
+            (Prefix::Column, KeyCode::Char('d')) => self.perform(Change::DeleteColumns { count }),
+            (Prefix::Register, KeyCode::Char('+')) => {
+                self.pending = pending.with_register('+');
+            }
+            (Prefix::Register, KeyCode::Char(name)) => {
+                self.command_buffer = format!("unknown register: {}", name);
+            }
+            (Prefix::Record, KeyCode::Char(name)) => self.start_recording(name),
+            (Prefix::Macro, KeyCode::Char(name)) => self.play_macro(name, count),
+            _ => {}
+        }
+    }
+
+    /// Finishes an operator with a motion, as in `dj` or `y3l`. Doubling
+    /// it, as in `dd` or `3yy`, acts on whole rows.
+    fn handle_operator_key(&mut self, pending: Pending, operator: Operator, key: KeyEvent) {
+        self.clear_command_buffer();
+        if key.code == KeyCode::Char(operator.key()) && key.modifiers.is_empty() {
+            self.operate(pending, None);
+        } else if key.code == KeyCode::Char('g') && key.modifiers.is_empty() {
+            self.pending = pending.with_prefix(Prefix::Go);
+        } else if let Some(motion) = Motion::from_key(key) {
+            self.run_motion(pending, motion);
+        }
+    }
+
+    fn handle_visual_mode(&mut self, key: KeyEvent) {
+        if let KeyCode::Char(digit) = key.code
+            && key.modifiers.is_empty()
+            && self.pending.takes_digit(digit)
+        {
+            self.pending.push_digit(digit);
+            return;
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        if pending.prefix() == Some(Prefix::Go) {
+            if key.code == KeyCode::Char('g') {
+                self.run_motion(pending, Motion::FirstRow);
+            }
+            return;
+        }
+        if let Some(motion) = Motion::from_key(key) {
+            self.run_motion(pending, motion);
+            return;
+        }
+
         match key.code {
             KeyCode::Esc => {
+                self.clear_command_buffer();
                 self.enter_normal_mode();
             }
-            KeyCode::Enter => {
-                self.enter_normal_mode();
-                self.move_cursor(0, 1);
+            KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.toggle_visual_kind(VisualKind::Column);
+            }
+            KeyCode::Char('v') if key.modifiers.is_empty() => {
+                self.toggle_visual_kind(VisualKind::Cell);
             }
-            KeyCode::Left => self.move_edit_cursor_left(),
-            KeyCode::Right => self.move_edit_cursor_right(),
-            KeyCode::Backspace => self.backspace_cell_value(),
-            KeyCode::Delete => self.delete_cell_value_forward(),
+            KeyCode::Char('V') => self.toggle_visual_kind(VisualKind::Row),
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.change_selection(VisualAction::FillDown);
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.change_selection(VisualAction::FillRight);
+            }
+            KeyCode::Char('g') => self.pending = pending.with_prefix(Prefix::Go),
+            KeyCode::Char('o') => self.swap_visual_anchor(),
+            KeyCode::Char('q') if key.modifiers.is_empty() && self.macros.recording().is_some() => {
+                self.stop_recording();
+            }
+            KeyCode::Char('y') => self.yank_selection(),
+            KeyCode::Char('d') | KeyCode::Char('x') => {
+                self.change_selection(VisualAction::Delete);
+            }
+            KeyCode::Char('p') => self.change_selection(VisualAction::Paste),
+            KeyCode::Char(':') => self.enter_command_mode(),
+            _ => self.clear_command_buffer(),
+        }
+    }
+
+    fn handle_insert_mode(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => self.finish_insert(false),
+            KeyCode::Enter => self.finish_insert(true),
+            KeyCode::Left => self.apply_edit(Edit::Left),
+            KeyCode::Right => self.apply_edit(Edit::Right),
+            KeyCode::Backspace => self.apply_edit(Edit::Backspace),
+            KeyCode::Delete => self.apply_edit(Edit::Delete),
+            KeyCode::F(4) => self.apply_edit(Edit::CycleAnchor),
             KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.insert_character_into_cell(c);
+                self.apply_edit(Edit::Char(c));
             }
             _ => {}
         }
     }
 
+    /// Starts an insert session with `start`, recording it for `.`.
+    pub(super) fn begin_insert(&mut self, start: InsertStart) {
+        match start {
+            InsertStart::Start => self.enter_insert_mode_at_start(),
+            InsertStart::End => self.enter_insert_mode_at_end(),
+            InsertStart::RowBelow => self.insert_row_below_and_edit(),
+            InsertStart::RowAbove => self.insert_row_above_and_edit(),
+            InsertStart::Operator { pending, motion } => self.run_operator(pending, motion),
+        }
+        self.record_insert(start);
+    }
+
+    /// Applies a key typed in insert mode to the cell being edited.
+    pub(super) fn apply_edit(&mut self, edit: Edit) {
+        match edit {
+            Edit::Char(c) => self.insert_character_into_cell(c),
+            Edit::Backspace => self.backspace_cell_value(),
+            Edit::Delete => self.delete_cell_value_forward(),
+            Edit::Left => self.move_edit_cursor_left(),
+            Edit::Right => self.move_edit_cursor_right(),
+            Edit::CycleAnchor => self.cycle_reference_anchor(),
+        }
+        self.record_edit(edit);
+    }
+
     fn handle_command_mode(&mut self, key: KeyEvent) {
         match key.code {
             KeyCode::Esc => {
@@ -160,8 +340,34 @@ impl App {
             }
             KeyCode::Enter => {
                 self.execute_command();
+                if matches!(self.mode, Mode::Command) {
+                    self.enter_normal_mode();
+                }
+            }
+            KeyCode::Backspace | KeyCode::Delete => {
+                if self.command_buffer.is_empty() {
+                    self.enter_normal_mode();
+                } else {
+                    self.command_buffer.pop();
+                }
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.command_buffer.push(c);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_search_mode(&mut self, key: KeyEvent, direction: SearchDirection) {
+        match key.code {
+            KeyCode::Esc => {
+                self.clear_command_buffer();
                 self.enter_normal_mode();
             }
+            KeyCode::Enter => {
+                self.enter_normal_mode();
+                self.start_search(direction);
+            }
             KeyCode::Backspace | KeyCode::Delete => {
                 if self.command_buffer.is_empty() {
                     self.enter_normal_mode();
@@ -176,8 +382,19 @@ impl App {
         }
     }
 
+    fn handle_confirm_mode(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => self.answer_substitution('q'),
+            KeyCode::Char(c) if key.modifiers.is_empty() => self.answer_substitution(c),
+            _ => {}
+        }
+    }
+
     fn execute_command(&mut self) {
         let binding = self.command_buffer.clone();
+        if self.try_substitute_command(&binding) {
+            return;
+        }
         let command: Vec<&str> = binding.split(' ').collect();
 
         match command[0] {
@@ -185,25 +402,102 @@ impl App {
                 let path = command.get(1).copied().unwrap_or(self.file_name.as_str());
                 self.handle_save_command(String::from(path));
             }
+            "e" | "edit" | "e!" | "edit!" => {
+                let path = command.get(1).copied().unwrap_or(self.file_name.as_str());
+                if self.modified && !command[0].ends_with('!') {
+                    self.command_buffer = "E37: unsaved changes (use :e!)".to_string();
+                } else {
+                    self.handle_open_command(String::from(path));
+                }
+            }
+            "export" => match command.get(1) {
+                Some(path) => self.handle_export_command(path),
+                None => self.command_buffer = "usage: export <file.csv>".to_string(),
+            },
             "q" | "quit" => self.quit(),
             "wq" => {
                 self.handle_save_command(self.file_name.clone());
                 self.quit()
             }
-            "cols" => {
-                let amount = command.get(1).copied().unwrap_or("8");
-                if let Ok(amount) = amount.parse::<usize>() {
-                    self.visible_cols = amount;
-                }
-            }
             "theme" => {
                 let name = command.get(1).copied();
                 self.handle_theme_command(name);
             }
+            "insertcol" => {
+                let (right, count) = parse_side_and_count(&command[1..]);
+                self.insert_columns_at_cursor(right, count);
+            }
+            "deletecol" => {
+                let count = command
+                    .get(1)
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(1);
+                self.delete_columns_at_cursor(count);
+            }
+            "colwidth" => match command.get(1).map(|width| width.parse::<u16>()) {
+                Some(Ok(width)) => {
+                    self.set_column_width(self.cursor.col, width);
+                    self.command_buffer =
+                        format!("column width {}", self.column_width(self.cursor.col));
+                }
+                Some(Err(_)) => self.command_buffer = "invalid width".to_string(),
+                None => {
+                    self.command_buffer =
+                        format!("column width {}", self.column_width(self.cursor.col));
+                }
+            },
+            "autofit" => match command.get(1).copied() {
+                Some("all") => self.autofit_all_columns(),
+                _ => self.autofit_column(self.cursor.col),
+            },
+            "format" => self.handle_format_command(&command[1..]),
+            "sheet" => self.handle_sheet_command(&command[1..]),
+            "noh" | "nohlsearch" => {
+                self.clear_search_highlight();
+                self.clear_command_buffer();
+            }
+            "set" => {
+                let option = command.get(1).copied();
+                self.handle_set_command(option);
+            }
             _ => self.command_buffer = format!("unknown command: {}", command[0]),
         }
     }
 
+    fn handle_set_command(&mut self, option: Option<&str>) {
+        let Some(option) = option else {
+            self.command_buffer = format!("clipboard={}", self.clipboard_backend.name());
+            return;
+        };
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (option.trim_end_matches('?'), None),
+        };
+        match (name, value) {
+            ("clipboard", None) => {
+                self.command_buffer = format!("clipboard={}", self.clipboard_backend.name());
+            }
+            ("clipboard", Some(value)) => match ClipboardBackend::from_name(value) {
+                Some(backend) => {
+                    self.clipboard_backend = backend;
+                    self.command_buffer = format!("clipboard={}", backend.name());
+                }
+                None => self.command_buffer = format!("unknown clipboard backend: {}", value),
+            },
+            ("search", None) => {
+                self.command_buffer = format!("search={}", self.search_scope.name());
+            }
+            ("search", Some(value)) => match SearchScope::from_name(value) {
+                Some(scope) => {
+                    self.search_scope = scope;
+                    self.command_buffer = format!("search={}", scope.name());
+                }
+                None => self.command_buffer = format!("unknown search scope: {}", value),
+            },
+            _ => self.command_buffer = format!("unknown option: {}", name),
+        }
+    }
+
     fn handle_theme_command(&mut self, name: Option<&str>) {
         match name {
             Some(name) => match self.load_theme_by_name(name) {
@@ -222,13 +516,13 @@ impl App {
         }
     }
 
-    fn move_cursor(&mut self, delta_col: i32, delta_row: i32) {
+    pub(super) fn move_cursor(&mut self, delta_col: i32, delta_row: i32) {
         self.cursor.row = apply_delta(self.cursor.row, delta_row);
         self.cursor.col = apply_delta(self.cursor.col, delta_col);
         self.ensure_cursor_visible();
     }
 
-    fn ensure_cursor_visible(&mut self) {
+    pub(super) fn ensure_cursor_visible(&mut self) {
         if self.cursor.row < self.viewport.row {
             self.viewport.row = self.cursor.row;
         } else if self.visible_rows > 0 {
@@ -242,28 +536,14 @@ impl App {
 
         if self.cursor.col < self.viewport.col {
             self.viewport.col = self.cursor.col;
-        } else if self.visible_cols > 0 {
-            let right_edge = self.viewport.col + self.visible_cols.saturating_sub(1);
-            if self.cursor.col > right_edge {
-                self.viewport.col = self.cursor.col + 1 - self.visible_cols;
-            }
-        } else {
-            self.viewport.col = self.cursor.col;
+        } else if self.cursor.col >= self.viewport.col + self.columns_fitting(self.viewport.col) {
+            self.viewport.col = self.first_column_showing(self.cursor.col);
         }
-    }
-
-    fn go_to_first_row(&mut self) {
-        self.cursor.row = 0;
-        self.ensure_cursor_visible();
-    }
-
-    fn go_to_last_row_with_value(&mut self) {
-        let last_row = self.cells.keys().map(|cell| cell.row).max().unwrap_or(0);
-        self.cursor.row = last_row;
-        self.ensure_cursor_visible();
+        self.visible_cols = self.columns_fitting(self.viewport.col);
     }
 
     fn insert_row_below_and_edit(&mut self) {
+        self.history.begin(self.cursor);
         let target = self.cursor.row.saturating_add(1);
         self.insert_row_at(target);
         self.cursor.row = target;
@@ -273,6 +553,7 @@ impl App {
     }
 
     fn insert_row_above_and_edit(&mut self) {
+        self.history.begin(self.cursor);
         let target = self.cursor.row;
         self.insert_row_at(target);
         self.cursor.col = 0;
@@ -281,74 +562,125 @@ impl App {
     }
 
     fn insert_row_at(&mut self, row: usize) {
-        let mut affected: Vec<(CellId, String)> = self
-            .cells
-            .iter()
-            .filter(|(cell, _)| cell.row >= row)
-            .map(|(cell, value)| (*cell, value.clone()))
-            .collect();
-        affected.sort_by(|a, b| match b.0.row.cmp(&a.0.row) {
-            Ordering::Equal => b.0.col.cmp(&a.0.col),
-            other => other,
-        });
-
-        for (cell, _) in &affected {
-            self.cells.remove(cell);
-        }
+        self.insert_rows(row, 1);
+    }
 
-        for (cell, value) in affected {
-            let new_cell = CellId::new(cell.row + 1, cell.col);
-            self.cells.insert(new_cell, value);
-        }
+    pub(super) fn insert_columns_at_cursor(&mut self, right: bool, count: usize) {
+        let count = count.max(1);
+        let at = if right {
+            self.cursor.col + 1
+        } else {
+            self.cursor.col
+        };
+        self.insert_columns(at, count);
+        self.cursor.col = at;
+        self.ensure_cursor_visible();
     }
 
-    fn delete_current_row(&mut self) {
-        let row = self.cursor.row;
-        self.cells.retain(|cell, _| cell.row != row);
+    pub(super) fn delete_columns_at_cursor(&mut self, count: usize) {
+        let first = self.cursor.col;
+        self.delete_columns(first, first + count.max(1) - 1);
+        self.ensure_cursor_visible();
+    }
 
-        let mut affected: Vec<(CellId, String)> = self
-            .cells
-            .iter()
-            .filter(|(cell, _)| cell.row > row)
-            .map(|(cell, value)| (*cell, value.clone()))
+    pub(super) fn undo(&mut self) {
+        let Some(revision) = self.history.undo() else {
+            self.command_buffer = "Already at oldest change".to_string();
+            return;
+        };
+        let count = revision.changes.len()
+            + revision
+                .elsewhere
+                .iter()
+                .map(|(_, changes)| changes.len())
+                .sum::<usize>();
+        let cells = revision
+            .changes
+            .into_iter()
+            .rev()
+            .map(|change| (change.cell, change.before))
             .collect();
-        affected.sort_by(|a, b| match a.0.row.cmp(&b.0.row) {
-            Ordering::Equal => a.0.col.cmp(&b.0.col),
-            other => other,
-        });
-
-        for (cell, _) in &affected {
-            self.cells.remove(cell);
-        }
-
-        for (cell, value) in affected {
-            let new_cell = CellId::new(cell.row - 1, cell.col);
-            self.cells.insert(new_cell, value);
-        }
-
-        if self.cursor.row > 0 && !self.row_exists(self.cursor.row) {
-            self.cursor.row = self.cursor.row.saturating_sub(1);
+        self.restore_cells(cells);
+        self.restore_elsewhere(&revision.elsewhere, true);
+        if let Some((before, _)) = revision.layout {
+            self.restore_layout(before);
         }
+        self.cursor = revision.cursor;
         self.ensure_cursor_visible();
+        self.command_buffer = format!("{} cell(s) changed; undo", count);
     }
 
-    fn row_exists(&self, row: usize) -> bool {
-        self.cells.keys().any(|cell| cell.row == row)
-    }
-
-    fn copy_current_cell_to_clipboard(&mut self) {
-        let csv = self.cell_to_csv(self.cursor);
-        self.clipboard = Some(csv.clone());
-        self.command_buffer = format!("yy -> {}", csv);
+    pub(super) fn redo(&mut self) {
+        let Some(revision) = self.history.redo() else {
+            self.command_buffer = "Already at newest change".to_string();
+            return;
+        };
+        let count = revision.changes.len()
+            + revision
+                .elsewhere
+                .iter()
+                .map(|(_, changes)| changes.len())
+                .sum::<usize>();
+        let cells = revision
+            .changes
+            .into_iter()
+            .map(|change| (change.cell, change.after))
+            .collect();
+        self.restore_cells(cells);
+        self.restore_elsewhere(&revision.elsewhere, false);
+        if let Some((_, after)) = revision.layout {
+            self.restore_layout(after);
+        }
+        self.cursor = revision.cursor;
+        self.ensure_cursor_visible();
+        self.command_buffer = format!("{} cell(s) changed; redo", count);
     }
 
-    fn paste_clipboard_into_cell(&mut self) {
-        if let Some(clip) = self.clipboard.clone() {
-            self.set_current_cell_value(clip.clone());
-            self.command_buffer = "p".to_string();
-        } else {
+    /// Pastes `count` copies: rows stacked one after another, blocks side
+    /// by side to the right. Rows go below the current row `after` the
+    /// cursor and above it otherwise; blocks land with their top-left
+    /// corner on the cursor either way. Returns false if the clipboard is
+    /// empty.
+    pub(super) fn paste_clipboard(&mut self, after: bool, count: usize) -> bool {
+        let Some(clip) = self.clipboard.clone() else {
             self.command_buffer = "clipboard empty".to_string();
+            return false;
+        };
+
+        let opened = self.history.begin(self.cursor);
+        match clip.kind {
+            ClipboardKind::Block => {
+                let width = clip.width().max(1);
+                let writes = (0..count)
+                    .flat_map(|copy| {
+                        let target = CellId::new(self.cursor.row, self.cursor.col + copy * width);
+                        paste_writes(&clip, target)
+                    })
+                    .collect();
+                self.set_cells(writes);
+            }
+            ClipboardKind::Rows => {
+                let row = if after {
+                    self.cursor.row + 1
+                } else {
+                    self.cursor.row
+                };
+                let height = clip.height();
+                self.insert_rows(row, height * count);
+                let writes = (0..count)
+                    .flat_map(|copy| paste_writes(&clip, CellId::new(row + copy * height, 0)))
+                    .collect();
+                self.set_cells(writes);
+                self.cursor.row = row;
+                self.cursor.col = 0;
+                self.ensure_cursor_visible();
+            }
+        }
+        if opened {
+            self.history.commit();
         }
+        self.command_buffer = format!("{} cells pasted", clip.cell_count() * count);
+        true
     }
 
     fn enter_insert_mode_at_start(&mut self) {
@@ -360,19 +692,35 @@ impl App {
         self.enter_insert_mode_with_cursor(len);
     }
 
-    fn enter_insert_mode_with_cursor(&mut self, cursor: usize) {
-        let len = self.current_cell_value().len();
+    pub(super) fn enter_insert_mode_with_cursor(&mut self, cursor: usize) {
+        // The whole insert session becomes one undo step, together with any
+        // row insertion that opened it.
+        self.history.begin(self.cursor);
+        self.editing = self.current_cell_value();
         self.mode = Mode::Insert(InsertState {
-            cursor: cursor.min(len),
+            cursor: cursor.min(self.editing.len()),
         });
         self.command_buffer.clear();
     }
 
-    fn enter_normal_mode(&mut self) {
+    /// Leaves any mode for normal mode. Text typed in insert mode is only
+    /// stored, and so parsed and evaluated, at this point.
+    pub(super) fn enter_normal_mode(&mut self) {
+        if let Mode::Insert(_) = self.mode {
+            let typed = std::mem::take(&mut self.editing);
+            if typed != self.current_cell_value() {
+                self.set_current_cell_value(typed);
+            }
+            self.history.commit();
+        }
+        self.command_selection = None;
         self.mode = Mode::Normal;
     }
 
+    /// Switches to the command line. Commands entered from visual mode act
+    /// on the selection.
     fn enter_command_mode(&mut self) {
+        self.command_selection = self.selection();
         self.mode = Mode::Command;
         self.command_buffer.clear();
     }
@@ -380,15 +728,16 @@ impl App {
     fn insert_character_into_cell(&mut self, ch: char) {
         let cursor = match self.mode {
             Mode::Insert(state) => state.cursor,
-            Mode::Normal => return,
-            Mode::Command => return,
+            Mode::Normal | Mode::Command | Mode::Search(_) | Mode::Confirm | Mode::Visual(_) => {
+                return;
+            }
         };
 
-        let mut value = self.current_cell_value();
+        let mut value = self.editing.clone();
         let insert_at = cursor.min(value.len());
         value.insert(insert_at, ch);
         let new_cursor = insert_at + ch.len_utf8();
-        self.set_current_cell_value(value);
+        self.editing = value;
 
         if let Mode::Insert(ref mut state) = self.mode {
             state.cursor = new_cursor;
@@ -398,20 +747,21 @@ impl App {
     fn backspace_cell_value(&mut self) {
         let cursor = match self.mode {
             Mode::Insert(state) => state.cursor,
-            Mode::Normal => return,
-            Mode::Command => return,
+            Mode::Normal | Mode::Command | Mode::Search(_) | Mode::Confirm | Mode::Visual(_) => {
+                return;
+            }
         };
 
         if cursor == 0 {
             return;
         }
 
-        let mut value = self.current_cell_value();
+        let mut value = self.editing.clone();
         let pos = cursor.min(value.len());
         if let Some((idx, ch)) = value[..pos].char_indices().next_back() {
             let end = idx + ch.len_utf8();
             value.drain(idx..end);
-            self.set_current_cell_value(value);
+            self.editing = value;
             if let Mode::Insert(ref mut state) = self.mode {
                 state.cursor = idx;
             }
@@ -421,48 +771,51 @@ impl App {
     fn delete_cell_value_forward(&mut self) {
         let cursor = match self.mode {
             Mode::Insert(state) => state.cursor,
-            Mode::Normal => return,
-            Mode::Command => return,
+            Mode::Normal | Mode::Command | Mode::Search(_) | Mode::Confirm | Mode::Visual(_) => {
+                return;
+            }
         };
 
-        let mut value = self.current_cell_value();
+        let mut value = self.editing.clone();
         let pos = cursor.min(value.len());
         if let Some((idx, ch)) = value[pos..].char_indices().next() {
             let start = pos + idx;
             let end = start + ch.len_utf8();
             value.drain(start..end);
-            self.set_current_cell_value(value);
+            self.editing = value;
         }
     }
 
     fn move_edit_cursor_left(&mut self) {
         let cursor = match self.mode {
             Mode::Insert(state) => state.cursor,
-            Mode::Normal => return,
-            Mode::Command => return,
+            Mode::Normal | Mode::Command | Mode::Search(_) | Mode::Confirm | Mode::Visual(_) => {
+                return;
+            }
         };
 
         if cursor == 0 {
             return;
         }
 
-        let value = self.current_cell_value();
+        let value = self.editing.clone();
         let pos = cursor.min(value.len());
-        if let Some((idx, _)) = value[..pos].char_indices().next_back() {
-            if let Mode::Insert(ref mut state) = self.mode {
-                state.cursor = idx;
-            }
+        if let Some((idx, _)) = value[..pos].char_indices().next_back()
+            && let Mode::Insert(ref mut state) = self.mode
+        {
+            state.cursor = idx;
         }
     }
 
     fn move_edit_cursor_right(&mut self) {
         let cursor = match self.mode {
             Mode::Insert(state) => state.cursor,
-            Mode::Normal => return,
-            Mode::Command => return,
+            Mode::Normal | Mode::Command | Mode::Search(_) | Mode::Confirm | Mode::Visual(_) => {
+                return;
+            }
         };
 
-        let value = self.current_cell_value();
+        let value = self.editing.clone();
         let pos = cursor.min(value.len());
         let new_cursor = if let Some((idx, ch)) = value[pos..].char_indices().next() {
             pos + idx + ch.len_utf8()
@@ -475,6 +828,26 @@ impl App {
         }
     }
 
+    /// Cycles `$` anchors on the reference under the edit cursor.
+    fn cycle_reference_anchor(&mut self) {
+        let cursor = match self.mode {
+            Mode::Insert(state) => state.cursor,
+            Mode::Normal | Mode::Command | Mode::Search(_) | Mode::Confirm | Mode::Visual(_) => {
+                return;
+            }
+        };
+
+        let value = self.editing.clone();
+        let Some((value, new_cursor)) = formula::cycle_anchor(&value, cursor) else {
+            return;
+        };
+        self.editing = value;
+
+        if let Mode::Insert(ref mut state) = self.mode {
+            state.cursor = new_cursor;
+        }
+    }
+
     fn current_cell_value(&self) -> String {
         let id = CellId::new(self.cursor.row, self.cursor.col);
         self.cells.get(&id).cloned().unwrap_or_default()
@@ -482,14 +855,10 @@ impl App {
 
     fn set_current_cell_value(&mut self, value: String) {
         let id = CellId::new(self.cursor.row, self.cursor.col);
-        if value.is_empty() {
-            self.cells.remove(&id);
-        } else {
-            self.cells.insert(id, value);
-        }
+        self.set_cell(id, value);
     }
 
-    fn row_to_csv(&self, row: usize) -> String {
+    pub(super) fn row_values(&self, row: usize) -> Vec<String> {
         let cols: Vec<usize> = self
             .cells
             .keys()
@@ -501,30 +870,99 @@ impl App {
         let mut fields = Vec::with_capacity(max_col + 1);
         for col in 0..=max_col {
             let id = CellId::new(row, col);
-            let value = self.cells.get(&id).cloned().unwrap_or_default();
-            fields.push(csv_escape(&value));
+            fields.push(self.cells.get(&id).cloned().unwrap_or_default());
         }
-        fields.join(",")
+        fields
     }
 
-    fn cell_to_csv(&self, cursor: Cursor) -> String {
-        let id = CellId::new(cursor.row, cursor.col);
-        let value = self.cells.get(&id).cloned().unwrap_or_default();
-        csv_escape(&value)
+    pub(super) fn row_to_csv(&self, row: usize) -> String {
+        let fields: Vec<String> = self
+            .row_values(row)
+            .iter()
+            .map(|value| csv_escape(value))
+            .collect();
+        fields.join(",")
     }
 
     fn handle_save_command(&mut self, path: String) {
         match self.save_sheet(path.as_str()) {
             Ok(path) => {
-                self.command_buffer = {
-                    self.file_name = path.clone();
-                    format!("\"{}\" written", path)
-                }
+                let caveat = self.csv_caveat(&path);
+                self.modified &= !caveat.is_empty();
+                self.command_buffer = format!("\"{}\" written{}", path, caveat);
+                self.file_name = path;
             }
             Err(err) => self.command_buffer = format!("E{}: {}", err.kind() as usize, err),
         }
     }
 
+    /// Writes the sheet as CSV without making it the current file, so the
+    /// next `:w` still saves everything in the native format.
+    fn handle_export_command(&mut self, path: &str) {
+        match self.save_csv(path) {
+            Ok(()) => {
+                self.command_buffer = format!("\"{}\" exported{}", path, self.csv_caveat(path))
+            }
+            Err(err) => self.command_buffer = format!("E{}: {}", err.kind() as usize, err),
+        }
+    }
+
+    pub(crate) fn handle_open_command(&mut self, path: String) {
+        match self.load_sheet(path.as_str()) {
+            Ok(rows) => {
+                self.modified = false;
+                self.command_buffer = format!("\"{}\" {}L", path, rows);
+                self.file_name = path;
+            }
+            // The workbook stays as it is, to be written there.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.command_buffer = format!("\"{}\" [New]", path);
+                self.file_name = path;
+            }
+            Err(err) => self.command_buffer = format!("E{}: {}", err.kind() as usize, err),
+        }
+    }
+
+    fn load_sheet(&mut self, path: &str) -> io::Result<usize> {
+        if path.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidFilename,
+                "No file name",
+            ));
+        }
+        if native::is_native(path) {
+            return self.load_native(path);
+        }
+        let text = fs::read_to_string(path)?;
+        let rows =
+            parse_csv(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let mut cells = HashMap::new();
+        for (row, fields) in rows.iter().enumerate() {
+            for (col, value) in fields.iter().enumerate() {
+                if !value.is_empty() {
+                    cells.insert(CellId::new(row, col), value.clone());
+                }
+            }
+        }
+        self.replace_cells(cells);
+        Ok(rows.len())
+    }
+
+    /// Starts over with a single sheet holding `cells`.
+    pub(super) fn replace_cells(&mut self, cells: HashMap<CellId, String>) {
+        self.sheets = vec![Sheet::named("Sheet1")];
+        self.active_sheet = 0;
+        self.cells = cells;
+        self.column_widths.clear();
+        self.formats.clear();
+        self.history.clear();
+        self.cursor = Cursor::default();
+        self.viewport = Viewport::default();
+        self.recalculate_all();
+    }
+
+    /// Saves in the native format for `.shits` files and as CSV otherwise.
     fn save_sheet(&self, path: &str) -> io::Result<String> {
         if path.is_empty() {
             return Err(io::Error::new(
@@ -532,7 +970,29 @@ impl App {
                 "No file name",
             ));
         }
-        let mut file = File::create(&path)?;
+        if native::is_native(path) {
+            self.save_native(path)?;
+        } else {
+            self.save_csv(path)?;
+        }
+        Ok(String::from(path))
+    }
+
+    /// A note for the status line when a CSV file at `path` holds only the
+    /// active sheet of several.
+    fn csv_caveat(&self, path: &str) -> String {
+        if native::is_native(path) || self.sheets.len() == 1 {
+            return String::new();
+        }
+        format!(
+            " (sheet {} only; save as .{} to keep every sheet)",
+            self.sheet_name(),
+            native::EXTENSION
+        )
+    }
+
+    fn save_csv(&self, path: &str) -> io::Result<()> {
+        let mut file = File::create(path)?;
         let max_row = self
             .cells
             .keys()
@@ -544,8 +1004,7 @@ impl App {
             let line = self.row_to_csv(row);
             writeln!(file, "{}", line)?;
         }
-        file.flush()?;
-        Ok(String::from(path))
+        file.flush()
     }
 
     fn clear_command_buffer(&mut self) {
@@ -561,11 +1020,112 @@ fn apply_delta(value: usize, delta: i32) -> usize {
     }
 }
 
-fn csv_escape(value: &str) -> String {
-    if value.contains(['"', ',', '\n']) {
-        let escaped = value.replace('"', "\"\"");
-        format!("\"{}\"", escaped)
-    } else {
-        value.to_string()
+/// Reads the optional `left`/`right` and count arguments of `:insertcol`.
+fn parse_side_and_count(args: &[&str]) -> (bool, usize) {
+    let mut right = false;
+    let mut count = 1;
+    for arg in args {
+        if *arg == "right" {
+            right = true;
+        } else if let Ok(amount) = arg.parse::<usize>() {
+            count = amount;
+        }
+    }
+    (right, count)
+}
+
+/// A count as a step for `move_cursor` and column resizing.
+fn clamp_delta(count: usize) -> i16 {
+    i16::try_from(count).unwrap_or(i16::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::super::{
+        App, CellId,
+        datetime::{self, DateKind},
+        value::Value,
+    };
+
+    fn press(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            app.on_key_event(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn typed_text_is_only_stored_when_insert_ends() {
+        let mut app = App::new();
+        let cell = CellId::new(0, 0);
+        press(&mut app, "i2024-0");
+        assert!(!app.cells.contains_key(&cell));
+        assert_eq!(app.cell_value(cell), Value::Empty);
+
+        press(&mut app, "3-05");
+        app.on_key_event(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        let serial = datetime::serial_from_date(2024, 3, 5).unwrap();
+        assert_eq!(app.cell_value(cell), Value::Date(serial, DateKind::Date));
+
+        app.undo();
+        assert!(!app.cells.contains_key(&cell));
+    }
+
+    #[test]
+    fn csv_saves_say_when_other_sheets_are_left_out() {
+        let path = env::temp_dir().join(format!("csv-caveat-{}.csv", process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let mut app = App::new();
+        app.handle_save_command(path.clone());
+        assert_eq!(app.command_buffer, format!("\"{}\" written", path));
+
+        app.handle_sheet_command(&["new", "Other"]);
+        app.handle_save_command(path.clone());
+        assert!(
+            app.command_buffer
+                .ends_with("(sheet Other only; save as .shits to keep every sheet)"),
+            "{}",
+            app.command_buffer
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn command(app: &mut App, line: &str) {
+        press(app, &format!(":{}", line));
+        app.on_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn edit_keeps_unsaved_changes() {
+        let dir = env::temp_dir();
+        let missing = dir.join(format!("edit-missing-{}.csv", process::id()));
+        let missing = missing.to_str().unwrap().to_string();
+        let saved = dir.join(format!("edit-saved-{}.csv", process::id()));
+        let saved = saved.to_str().unwrap().to_string();
+        let cell = CellId::new(0, 0);
+        let mut app = App::new();
+        app.set_cell(cell, "1".to_string());
+
+        command(&mut app, &format!("e {}", missing));
+        assert_eq!(app.command_buffer, "E37: unsaved changes (use :e!)");
+        assert!(app.file_name.is_empty());
+
+        command(&mut app, &format!("e! {}", missing));
+        assert_eq!(app.command_buffer, format!("\"{}\" [New]", missing));
+        assert_eq!(app.file_name, missing);
+        assert_eq!(app.cell_value(cell), Value::Number(1.0));
+
+        command(&mut app, &format!("w {}", saved));
+        app.set_cell(cell, "2".to_string());
+        command(&mut app, "e");
+        assert_eq!(app.cell_value(cell), Value::Number(2.0));
+        command(&mut app, "e!");
+        assert_eq!(app.cell_value(cell), Value::Number(1.0));
+        command(&mut app, "e");
+        assert_eq!(app.command_buffer, format!("\"{}\" 1L", saved));
+        fs::remove_file(&saved).unwrap();
     }
 }
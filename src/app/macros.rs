@@ -0,0 +1,144 @@
+//! Macros: `qa` records keys into register `a` until the next `q`, and `@a`
+//! plays them back through `on_key_event` as if they were typed again.
+
+use std::collections::HashMap;
+
+use crossterm::event::KeyEvent;
+
+use super::App;
+
+/// How deep macros may run other macros, so one that calls itself stops.
+const MAX_DEPTH: usize = 100;
+
+#[derive(Debug, Default)]
+pub(crate) struct Macros {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    /// The register being recorded into and the keys so far.
+    recording: Option<(char, Vec<KeyEvent>)>,
+    /// The register `@@` plays.
+    last: Option<char>,
+    depth: usize,
+    /// Set when playback went too deep, to stop every macro still running.
+    aborted: bool,
+}
+
+impl Macros {
+    pub(crate) fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+}
+
+impl App {
+    /// Keeps `key` for the macro being recorded. Keys played back from a
+    /// macro are not kept, so recording `@a` records just that.
+    pub(super) fn record_key(&mut self, key: KeyEvent) {
+        if self.macros.depth > 0 {
+            return;
+        }
+        if let Some((_, keys)) = &mut self.macros.recording {
+            keys.push(key);
+        }
+    }
+
+    /// `q{register}`. An uppercase name appends to the lowercase register,
+    /// as in vim.
+    pub(super) fn start_recording(&mut self, name: char) {
+        let register = name.to_ascii_lowercase();
+        if !register.is_ascii_lowercase() {
+            self.command_buffer = format!("unknown register: {}", name);
+            return;
+        }
+        let keys = match name.is_ascii_uppercase() {
+            true => self
+                .macros
+                .registers
+                .get(&register)
+                .cloned()
+                .unwrap_or_default(),
+            false => Vec::new(),
+        };
+        self.macros.recording = Some((register, keys));
+    }
+
+    /// The `q` that ends a recording.
+    pub(super) fn stop_recording(&mut self) {
+        let Some((register, mut keys)) = self.macros.recording.take() else {
+            return;
+        };
+        // The `q` itself was recorded on its way in, unless a macro typed it.
+        if self.macros.depth == 0 {
+            keys.pop();
+        }
+        self.command_buffer = format!("recorded @{}", register);
+        self.macros.registers.insert(register, keys);
+    }
+
+    /// `@{register}`, or `@@` for the last register played, `count` times.
+    pub(super) fn play_macro(&mut self, name: char, count: usize) {
+        let register = match name {
+            '@' => match self.macros.last {
+                Some(register) => register,
+                None => {
+                    self.command_buffer = "E748: No previously used register".to_string();
+                    return;
+                }
+            },
+            name if name.is_ascii_alphabetic() => name.to_ascii_lowercase(),
+            name => {
+                self.command_buffer = format!("unknown register: {}", name);
+                return;
+            }
+        };
+        let Some(keys) = self.macros.registers.get(&register).cloned() else {
+            self.command_buffer = format!("register {} is empty", register);
+            return;
+        };
+        if self.macros.depth == MAX_DEPTH {
+            self.macros.aborted = true;
+            self.command_buffer = "E169: Command too recursive".to_string();
+            return;
+        }
+
+        self.macros.last = Some(register);
+        self.macros.depth += 1;
+        'play: for _ in 0..count {
+            for &key in &keys {
+                if self.macros.aborted {
+                    break 'play;
+                }
+                self.on_key_event(key);
+            }
+        }
+        self.macros.depth -= 1;
+        if self.macros.depth == 0 {
+            self.macros.aborted = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::super::{App, Mode};
+
+    fn press(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            app.on_key_event(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn q_ends_a_recording_in_visual_mode() {
+        let mut app = App::new();
+        press(&mut app, "qavjq");
+        assert_eq!(app.macros.recording(), None);
+        assert!(matches!(app.mode, Mode::Visual(_)));
+        assert_eq!(app.macros.registers[&'a'].len(), 2);
+
+        app.on_key_event(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        press(&mut app, "@a");
+        assert!(matches!(app.mode, Mode::Visual(_)));
+        assert_eq!(app.cursor.row, 2);
+    }
+}
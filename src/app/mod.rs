@@ -1,6 +1,27 @@
+mod calc;
+mod clipboard;
+mod columns;
+mod csv;
+mod datetime;
+mod deps;
 mod events;
+mod format;
+mod formula;
+mod history;
 mod keymap;
+mod macros;
+mod motion;
+mod native;
+mod operator;
+mod pending;
 mod render;
+mod repeat;
+mod search;
+mod sheets;
+mod structure;
+mod substitute;
+mod value;
+mod visual;
 
 use std::{
     collections::HashMap,
@@ -8,12 +29,23 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use calc::Values;
+use clipboard::{Clipboard, ClipboardBackend};
 use color_eyre::Result;
+use deps::{DependencyGraph, SheetCell};
+use format::NumberFormat;
+use history::History;
+use macros::Macros;
+use pending::Pending;
 use ratatui::{DefaultTerminal, style::Color};
+use repeat::{Change, InsertSession};
+use search::{Search, SearchDirection, SearchScope};
 use serde::Deserialize;
+use sheets::Sheet;
+use substitute::Substitution;
+use visual::Selection;
 
 const DEFAULT_VISIBLE_ROWS: usize = 12;
-const DEFAULT_VISIBLE_COLS: usize = 8;
 
 #[derive(Debug)]
 pub struct App {
@@ -21,12 +53,35 @@ pub struct App {
     mode: Mode,
     visible_rows: usize,
     visible_cols: usize,
+    grid_width: u16,
     viewport: Viewport,
+    sheets: Vec<Sheet>,
+    active_sheet: usize,
     cells: HashMap<CellId, String>,
+    column_widths: HashMap<usize, u16>,
+    formats: Vec<(Selection, NumberFormat)>,
+    computed: Values,
+    dependencies: DependencyGraph,
+    cycles: Vec<Vec<SheetCell>>,
     cursor: Cursor,
     file_name: String,
+    /// Whether the workbook changed since it was last loaded or saved whole.
+    modified: bool,
     command_buffer: String,
-    clipboard: Option<String>,
+    command_selection: Option<Selection>,
+    pending: Pending,
+    last_change: Option<Change>,
+    insert_session: Option<InsertSession>,
+    /// The text of the cell being typed in insert mode, stored into the
+    /// cell when the session ends.
+    editing: String,
+    macros: Macros,
+    search: Option<Search>,
+    search_scope: SearchScope,
+    substitution: Option<Substitution>,
+    clipboard: Option<Clipboard>,
+    clipboard_backend: ClipboardBackend,
+    history: History,
     theme: Theme,
 }
 
@@ -36,13 +91,33 @@ impl App {
             running: false,
             mode: Mode::Normal,
             visible_rows: DEFAULT_VISIBLE_ROWS,
-            visible_cols: DEFAULT_VISIBLE_COLS,
+            visible_cols: 0,
+            grid_width: 0,
             viewport: Viewport::default(),
+            sheets: vec![Sheet::named("Sheet1")],
+            active_sheet: 0,
             cells: HashMap::new(),
+            column_widths: HashMap::new(),
+            formats: Vec::new(),
+            computed: Values::default(),
+            dependencies: DependencyGraph::default(),
+            cycles: Vec::new(),
             cursor: Cursor::default(),
             file_name: String::new(),
+            modified: false,
             command_buffer: String::new(),
+            command_selection: None,
+            pending: Pending::default(),
+            last_change: None,
+            insert_session: None,
+            editing: String::new(),
+            macros: Macros::default(),
+            search: None,
+            search_scope: SearchScope::Raw,
+            substitution: None,
             clipboard: None,
+            clipboard_backend: ClipboardBackend::Osc52,
+            history: History::default(),
             theme: Theme::default(),
         }
     }
@@ -121,6 +196,10 @@ enum Mode {
     Normal,
     Insert(InsertState),
     Command,
+    Search(SearchDirection),
+    /// Asking whether to make each replacement of a `:s///c`.
+    Confirm,
+    Visual(VisualState),
 }
 
 impl fmt::Display for Mode {
@@ -129,6 +208,13 @@ impl fmt::Display for Mode {
             Mode::Normal => write!(f, "NORMAL"),
             Mode::Insert(_) => write!(f, "INSERT"),
             Mode::Command => write!(f, "COMMAND"),
+            Mode::Search(_) => write!(f, "SEARCH"),
+            Mode::Confirm => write!(f, "CONFIRM"),
+            Mode::Visual(state) => match state.kind {
+                VisualKind::Cell => write!(f, "VISUAL"),
+                VisualKind::Row => write!(f, "VISUAL LINE"),
+                VisualKind::Column => write!(f, "VISUAL COLUMN"),
+            },
         }
     }
 }
@@ -138,6 +224,19 @@ struct InsertState {
     cursor: usize,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct VisualState {
+    anchor: Cursor,
+    kind: VisualKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VisualKind {
+    Cell,
+    Row,
+    Column,
+}
+
 #[derive(Debug)]
 struct Theme {
     global_bg: Color,
@@ -156,6 +255,10 @@ struct Theme {
     selected_row_bg: Color,
     selected_col_fg: Color,
     selected_col_bg: Color,
+    selection_fg: Color,
+    selection_bg: Color,
+    search_match_fg: Color,
+    search_match_bg: Color,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -176,6 +279,10 @@ struct ThemeConfig {
     selected_row_bg: Option<[u8; 3]>,
     selected_col_fg: Option<[u8; 3]>,
     selected_col_bg: Option<[u8; 3]>,
+    selection_fg: Option<[u8; 3]>,
+    selection_bg: Option<[u8; 3]>,
+    search_match_fg: Option<[u8; 3]>,
+    search_match_bg: Option<[u8; 3]>,
 }
 
 impl Default for Theme {
@@ -197,6 +304,10 @@ impl Default for Theme {
             selected_row_bg: Color::Rgb(32, 32, 32),
             selected_col_fg: Color::Rgb(255, 255, 255),
             selected_col_bg: Color::Rgb(32, 32, 32),
+            selection_fg: Color::Rgb(255, 255, 255),
+            selection_bg: Color::Rgb(68, 61, 107),
+            search_match_fg: Color::Rgb(0, 0, 0),
+            search_match_bg: Color::Rgb(110, 180, 110),
         }
     }
 }
@@ -219,6 +330,10 @@ impl ThemeConfig {
         apply_color(&mut theme.selected_row_bg, self.selected_row_bg)?;
         apply_color(&mut theme.selected_col_fg, self.selected_col_fg)?;
         apply_color(&mut theme.selected_col_bg, self.selected_col_bg)?;
+        apply_color(&mut theme.selection_fg, self.selection_fg)?;
+        apply_color(&mut theme.selection_bg, self.selection_bg)?;
+        apply_color(&mut theme.search_match_fg, self.search_match_fg)?;
+        apply_color(&mut theme.search_match_bg, self.search_match_bg)?;
         Ok(())
     }
 }
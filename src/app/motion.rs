@@ -0,0 +1,156 @@
+//! The motions shared by plain navigation, visual mode and operators, so
+//! `j` moves the cursor, extends a selection and makes `dj` delete two rows.
+
+use std::collections::HashSet;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::{App, Cursor, pending::Pending};
+
+/// Columns `w` and `b` jump over.
+const WORD_COLUMNS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    /// `0`, the first column.
+    RowStart,
+    /// `$`, the last column holding a value in the current row.
+    RowEnd,
+    /// `gg`, or row N with a count.
+    FirstRow,
+    /// `G`, the last row holding a value, or row N with a count.
+    LastRow,
+    /// `}`, the next empty row after a block of filled ones.
+    NextEmptyRow,
+    /// `{`, the previous empty row before a block of filled ones.
+    PreviousEmptyRow,
+}
+
+impl Motion {
+    /// The motion a single key stands for. `gg` takes two keys and is
+    /// handled with the other `g` commands.
+    pub(crate) fn from_key(key: KeyEvent) -> Option<Self> {
+        if key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            return None;
+        }
+        Some(match key.code {
+            KeyCode::Left | KeyCode::Char('h') => Self::Left,
+            KeyCode::Right | KeyCode::Char('l') => Self::Right,
+            KeyCode::Up | KeyCode::Char('k') => Self::Up,
+            KeyCode::Down | KeyCode::Char('j') | KeyCode::Enter => Self::Down,
+            KeyCode::Char('w') => Self::WordForward,
+            KeyCode::Char('b') => Self::WordBackward,
+            KeyCode::Home | KeyCode::Char('0') => Self::RowStart,
+            KeyCode::End | KeyCode::Char('$') => Self::RowEnd,
+            KeyCode::Char('G') => Self::LastRow,
+            KeyCode::Char('}') => Self::NextEmptyRow,
+            KeyCode::Char('{') => Self::PreviousEmptyRow,
+            _ => return None,
+        })
+    }
+
+    /// Whether an operator over this motion takes whole rows, as `dj` does,
+    /// rather than the cells of the current row.
+    pub(crate) fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Self::Up
+                | Self::Down
+                | Self::FirstRow
+                | Self::LastRow
+                | Self::NextEmptyRow
+                | Self::PreviousEmptyRow
+        )
+    }
+
+    /// Whether an operator over this motion includes the cell it lands on.
+    /// `dl` clears one cell and `d$` clears through the last one.
+    pub(crate) fn is_inclusive(self) -> bool {
+        matches!(
+            self,
+            Self::RowEnd | Self::Up | Self::Down | Self::FirstRow | Self::LastRow
+        )
+    }
+}
+
+impl App {
+    /// Where `motion` takes the cursor, repeated by the count in `pending`.
+    pub(super) fn motion_target(&self, motion: Motion, pending: Pending) -> Cursor {
+        let count = pending.count();
+        let Cursor { row, col } = self.cursor;
+        let (row, col) = match motion {
+            Motion::Left => (row, col.saturating_sub(count)),
+            Motion::Right => (row, col.saturating_add(count)),
+            Motion::Up => (row.saturating_sub(count), col),
+            Motion::Down => (row.saturating_add(count), col),
+            Motion::WordForward => (row, col.saturating_add(WORD_COLUMNS * count)),
+            Motion::WordBackward => (row, col.saturating_sub(WORD_COLUMNS * count)),
+            Motion::RowStart => (row, 0),
+            Motion::RowEnd => {
+                let last = self.cells.keys().filter(|cell| cell.row == row);
+                (row, last.map(|cell| cell.col).max().unwrap_or(0))
+            }
+            Motion::FirstRow if pending.has_count() => (count - 1, col),
+            Motion::FirstRow => (0, col),
+            Motion::LastRow if pending.has_count() => (count - 1, col),
+            Motion::LastRow => {
+                let last = self.cells.keys().map(|cell| cell.row).max();
+                (last.unwrap_or(0), col)
+            }
+            Motion::NextEmptyRow => {
+                let filled = self.filled_rows();
+                let last = filled.iter().copied().max().unwrap_or(0);
+                let mut target = row;
+                for _ in 0..count {
+                    target += 1;
+                    while !filled.contains(&target) && target < last {
+                        target += 1;
+                    }
+                    while filled.contains(&target) {
+                        target += 1;
+                    }
+                }
+                (target, col)
+            }
+            Motion::PreviousEmptyRow => {
+                let filled = self.filled_rows();
+                let mut target = row;
+                for _ in 0..count {
+                    target = target.saturating_sub(1);
+                    while !filled.contains(&target) && target > 0 {
+                        target -= 1;
+                    }
+                    while filled.contains(&target) && target > 0 {
+                        target -= 1;
+                    }
+                }
+                (target, col)
+            }
+        };
+        Cursor { row, col }
+    }
+
+    /// Moves the cursor by `motion`, or applies the operator waiting in
+    /// `pending` over the cells it covers.
+    pub(super) fn run_motion(&mut self, pending: Pending, motion: Motion) {
+        if pending.operator().is_some() {
+            self.operate(pending, Some(motion));
+            return;
+        }
+        self.cursor = self.motion_target(motion, pending);
+        self.ensure_cursor_visible();
+    }
+
+    fn filled_rows(&self) -> HashSet<usize> {
+        self.cells.keys().map(|cell| cell.row).collect()
+    }
+}
@@ -0,0 +1,343 @@
+//! The `.shits` file format: the whole workbook as JSON, so sheets,
+//! formulas, column widths and number formats survive a save, unlike CSV.
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    App, CellId, Cursor,
+    format::NumberFormat,
+    formula,
+    render::column_name,
+    sheets::{self, Sheet},
+    visual::Selection,
+};
+
+pub(crate) const EXTENSION: &str = "shits";
+const VERSION: u32 = 1;
+
+/// Read before the rest, so a file from a newer build gets a clear error
+/// rather than a parse error.
+#[derive(Debug, Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WorkbookFile {
+    version: u32,
+    /// Index of the sheet shown when the file is opened.
+    #[serde(default)]
+    active: usize,
+    sheets: Vec<SheetData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SheetData {
+    name: String,
+    #[serde(default)]
+    cursor: Option<String>,
+    #[serde(default)]
+    cells: Vec<CellEntry>,
+    /// Widths keyed by column letters, for columns not at the default.
+    #[serde(default)]
+    column_widths: Vec<ColumnWidth>,
+    /// Formats in the order they were applied; later ones win.
+    #[serde(default)]
+    formats: Vec<FormatEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CellEntry {
+    cell: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ColumnWidth {
+    column: String,
+    width: u16,
+}
+
+/// A formatted block. A missing `bottom` or `right` runs to the end of the
+/// sheet, as whole row and column selections do.
+#[derive(Debug, Serialize, Deserialize)]
+struct FormatEntry {
+    top: usize,
+    left: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bottom: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    right: Option<usize>,
+    format: NumberFormat,
+}
+
+/// A sheet read from a file, checked before anything in the app changes.
+struct LoadedSheet {
+    name: String,
+    cursor: Option<CellId>,
+    cells: HashMap<CellId, String>,
+    column_widths: Vec<(usize, u16)>,
+    formats: Vec<(Selection, NumberFormat)>,
+}
+
+/// Whether `path` should be read and written in the native format.
+pub(crate) fn is_native(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(EXTENSION))
+}
+
+impl App {
+    pub(super) fn save_native(&self, path: &str) -> io::Result<()> {
+        let sheets = self
+            .sheets
+            .iter()
+            .enumerate()
+            .map(|(index, sheet)| {
+                if index == self.active_sheet {
+                    sheet_data(
+                        &sheet.name,
+                        self.cursor,
+                        &self.cells,
+                        &self.column_widths,
+                        &self.formats,
+                    )
+                } else {
+                    sheet_data(
+                        &sheet.name,
+                        sheet.cursor,
+                        &sheet.cells,
+                        &sheet.column_widths,
+                        &sheet.formats,
+                    )
+                }
+            })
+            .collect();
+        let file = WorkbookFile {
+            version: VERSION,
+            active: self.active_sheet,
+            sheets,
+        };
+        let json = serde_json::to_string_pretty(&file).map_err(io::Error::other)?;
+        fs::write(path, json + "\n")
+    }
+
+    /// Replaces the workbook with the contents of a native file and returns
+    /// the row count of the sheet it opens on.
+    pub(super) fn load_native(&mut self, path: &str) -> io::Result<usize> {
+        let text = fs::read_to_string(path)?;
+        let header: Header = serde_json::from_str(&text).map_err(invalid_data)?;
+        if header.version > VERSION {
+            return Err(invalid(format!(
+                "file version {} is newer than this build supports",
+                header.version
+            )));
+        }
+        let file: WorkbookFile = serde_json::from_str(&text).map_err(invalid_data)?;
+        if file.sheets.is_empty() {
+            return Err(invalid("file has no sheets".to_string()));
+        }
+
+        let mut sheets: Vec<LoadedSheet> = Vec::with_capacity(file.sheets.len());
+        for data in file.sheets {
+            let sheet = load_sheet_data(data)?;
+            sheets::check_name(&sheet.name)
+                .map_err(|err| invalid(format!("{:?}: {}", sheet.name, err)))?;
+            if sheets
+                .iter()
+                .any(|other| other.name.eq_ignore_ascii_case(&sheet.name))
+            {
+                return Err(invalid(format!("duplicate sheet name: {}", sheet.name)));
+            }
+            sheets.push(sheet);
+        }
+
+        for (index, sheet) in sheets.into_iter().enumerate() {
+            if index == 0 {
+                self.replace_cells(sheet.cells);
+                self.sheets[0].name = sheet.name;
+            } else {
+                self.park_sheet();
+                self.sheets.push(Sheet::named(sheet.name));
+                self.unpark_sheet(index);
+                self.cells = sheet.cells;
+            }
+            for (col, width) in sheet.column_widths {
+                self.set_column_width(col, width);
+            }
+            self.formats = sheet.formats;
+            if let Some(cursor) = sheet.cursor {
+                self.cursor = Cursor {
+                    row: cursor.row,
+                    col: cursor.col,
+                };
+            }
+        }
+        self.recalculate_all();
+        let active = file.active.min(self.sheets.len() - 1);
+        if active != self.active_sheet {
+            self.park_sheet();
+            self.unpark_sheet(active);
+        }
+        self.ensure_cursor_visible();
+        Ok(self
+            .cells
+            .keys()
+            .map(|cell| cell.row + 1)
+            .max()
+            .unwrap_or(0))
+    }
+}
+
+fn sheet_data(
+    name: &str,
+    cursor: Cursor,
+    cells: &HashMap<CellId, String>,
+    column_widths: &HashMap<usize, u16>,
+    formats: &[(Selection, NumberFormat)],
+) -> SheetData {
+    let mut cells: Vec<(&CellId, &String)> = cells.iter().collect();
+    cells.sort_by_key(|(cell, _)| (cell.row, cell.col));
+    let mut widths: Vec<(&usize, &u16)> = column_widths.iter().collect();
+    widths.sort();
+
+    SheetData {
+        name: name.to_string(),
+        cursor: Some(cell_label(CellId::new(cursor.row, cursor.col))),
+        cells: cells
+            .into_iter()
+            .map(|(cell, raw)| CellEntry {
+                cell: cell_label(*cell),
+                value: raw.clone(),
+            })
+            .collect(),
+        column_widths: widths
+            .into_iter()
+            .map(|(col, width)| ColumnWidth {
+                column: column_name(*col),
+                width: *width,
+            })
+            .collect(),
+        formats: formats
+            .iter()
+            .map(|(area, format)| FormatEntry {
+                top: area.top,
+                left: area.left,
+                bottom: (area.bottom != usize::MAX).then_some(area.bottom),
+                right: (area.right != usize::MAX).then_some(area.right),
+                format: format.clone(),
+            })
+            .collect(),
+    }
+}
+
+fn load_sheet_data(data: SheetData) -> io::Result<LoadedSheet> {
+    let mut cells = HashMap::with_capacity(data.cells.len());
+    for entry in data.cells {
+        let cell = parse_label(&entry.cell)?;
+        if !entry.value.is_empty() {
+            cells.insert(cell, entry.value);
+        }
+    }
+    let column_widths = data
+        .column_widths
+        .into_iter()
+        .map(|entry| Ok((parse_label(&format!("{}1", entry.column))?.col, entry.width)))
+        .collect::<io::Result<_>>()?;
+    let formats = data
+        .formats
+        .into_iter()
+        .map(|entry| {
+            let area = Selection {
+                top: entry.top,
+                left: entry.left,
+                bottom: entry.bottom.unwrap_or(usize::MAX),
+                right: entry.right.unwrap_or(usize::MAX),
+            };
+            (area, entry.format)
+        })
+        .collect();
+    Ok(LoadedSheet {
+        name: data.name,
+        cursor: data.cursor.as_deref().map(parse_label).transpose()?,
+        cells,
+        column_widths,
+        formats,
+    })
+}
+
+fn cell_label(cell: CellId) -> String {
+    format!("{}{}", column_name(cell.col), cell.row + 1)
+}
+
+fn parse_label(label: &str) -> io::Result<CellId> {
+    formula::parse_cell_label(label).ok_or_else(|| invalid(format!("bad cell label: {}", label)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_data(err: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io, process};
+
+    use super::super::{App, CellId, value::Value};
+
+    /// Writes `json` to a file of its own and loads it into a new app.
+    fn load(name: &str, json: &str) -> (App, io::Result<usize>) {
+        let path = env::temp_dir().join(format!("{}-{}.shits", name, process::id()));
+        fs::write(&path, json).unwrap();
+        let mut app = App::new();
+        let result = app.load_native(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        (app, result)
+    }
+
+    fn workbook(names: &[&str]) -> String {
+        let sheets: Vec<String> = names
+            .iter()
+            .map(|name| {
+                format!(
+                    r#"{{"name": {:?}, "cells": [{{"cell": "A1", "value": "1"}}]}}"#,
+                    name
+                )
+            })
+            .collect();
+        format!(r#"{{"version": 1, "sheets": [{}]}}"#, sheets.join(", "))
+    }
+
+    #[test]
+    fn loads_every_sheet() {
+        let (mut app, result) = load("loads", &workbook(&["Main", "Q1 Totals"]));
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(app.sheet_name(), "Main");
+        app.switch_sheet(1);
+        assert_eq!(app.sheet_name(), "Q1 Totals");
+        assert_eq!(app.cell_value(CellId::new(0, 0)), Value::Number(1.0));
+    }
+
+    #[test]
+    fn rejects_bad_sheet_names() {
+        for names in [&["Main", "main"][..], &[""], &["  "], &["a!b"], &["it's"]] {
+            let (_, result) = load("names", &workbook(names));
+            let err = result.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", names);
+        }
+        let (_, result) = load("missing", r#"{"version": 1, "sheets": [{"cells": []}]}"#);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let (_, result) = load("newer", r#"{"version": 2, "sheets": []}"#);
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("newer"), "{}", err);
+    }
+}
@@ -0,0 +1,174 @@
+//! What `d`, `y` and `c` do to the cells a motion covers.
+
+use super::{
+    App, CellId, Cursor,
+    clipboard::{Clipboard, ClipboardKind},
+    motion::Motion,
+    pending::{Operator, Pending},
+    repeat::{Change, InsertStart},
+};
+
+/// The cells an operator acts on: whole rows for linewise motions such as
+/// `j`, or a run of cells in the cursor's row for `l`, `w` or `$`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Span {
+    Rows {
+        top: usize,
+        bottom: usize,
+    },
+    Cells {
+        row: usize,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl App {
+    /// The span between the cursor and `target`. Exclusive motions leave
+    /// out the cell they land on, so `dh` in the first column does nothing.
+    pub(super) fn motion_span(&self, motion: Motion, target: Cursor) -> Option<Span> {
+        let cursor = self.cursor;
+        if motion.is_linewise() {
+            let (mut top, mut bottom) = (cursor.row.min(target.row), cursor.row.max(target.row));
+            // `d}` stops short of the empty row it lands on.
+            if !motion.is_inclusive()
+                && target.row != cursor.row
+                && !self.row_has_values(target.row)
+            {
+                if target.row > cursor.row {
+                    bottom -= 1;
+                } else {
+                    top += 1;
+                }
+            }
+            return Some(Span::Rows { top, bottom });
+        }
+
+        let (left, right) = match (motion.is_inclusive(), target.col.cmp(&cursor.col)) {
+            (true, _) => (cursor.col.min(target.col), cursor.col.max(target.col)),
+            (false, std::cmp::Ordering::Greater) => (cursor.col, target.col - 1),
+            (false, std::cmp::Ordering::Less) => (target.col, cursor.col - 1),
+            (false, std::cmp::Ordering::Equal) => return None,
+        };
+        Some(Span::Cells {
+            row: cursor.row,
+            left,
+            right,
+        })
+    }
+
+    /// Applies the operator in `pending` over `motion`, or over as many rows
+    /// as the count when there is no motion. Deletes and changes are
+    /// remembered for `.`.
+    pub(super) fn operate(&mut self, pending: Pending, motion: Option<Motion>) {
+        match pending.operator() {
+            Some(Operator::Yank) => self.run_operator(pending, motion),
+            Some(Operator::Delete) => self.perform(Change::Operator { pending, motion }),
+            Some(Operator::Change) => self.begin_insert(InsertStart::Operator { pending, motion }),
+            None => {}
+        }
+    }
+
+    pub(super) fn run_operator(&mut self, pending: Pending, motion: Option<Motion>) {
+        let Some(operator) = pending.operator() else {
+            return;
+        };
+        let span = match motion {
+            Some(motion) => {
+                let target = self.motion_target(motion, pending);
+                self.motion_span(motion, target)
+            }
+            None => Some(Span::Rows {
+                top: self.cursor.row,
+                bottom: self.cursor.row + pending.count() - 1,
+            }),
+        };
+        if let Some(span) = span {
+            self.apply_operator(operator, span);
+        }
+    }
+
+    fn apply_operator(&mut self, operator: Operator, span: Span) {
+        match (operator, span) {
+            (Operator::Delete, Span::Rows { top, bottom }) => {
+                self.cursor.row = top;
+                self.delete_rows(top, bottom);
+            }
+            (Operator::Yank, Span::Rows { top, bottom }) => {
+                self.yank_rows(top, bottom);
+                self.cursor.row = top;
+                self.ensure_cursor_visible();
+            }
+            (Operator::Yank, Span::Cells { row, left, right }) => {
+                let origin = CellId::new(row, left);
+                let clipboard = Clipboard {
+                    rows: self.block_values(origin, CellId::new(row, right)),
+                    origin,
+                    kind: ClipboardKind::Block,
+                };
+                let count = clipboard.cell_count();
+                self.clipboard = Some(clipboard);
+                self.cursor.col = left;
+                self.ensure_cursor_visible();
+                self.command_buffer = format!("{} cells yanked", count);
+                self.publish_clipboard();
+            }
+            (Operator::Delete, span) => {
+                let count = self.clear_span(span);
+                self.command_buffer = format!("{} cells cleared", count);
+            }
+            (Operator::Change, span) => {
+                // The clearing and the insert session that follows undo as
+                // one step.
+                self.history.begin(self.cursor);
+                self.clear_span(span);
+                self.enter_insert_mode_with_cursor(0);
+            }
+        }
+    }
+
+    /// Yanks rows `top..=bottom` as whole rows, to be pasted linewise.
+    pub(super) fn yank_rows(&mut self, top: usize, bottom: usize) {
+        self.clipboard = Some(Clipboard {
+            rows: (top..=bottom).map(|row| self.row_values(row)).collect(),
+            origin: CellId::new(top, 0),
+            kind: ClipboardKind::Rows,
+        });
+        self.command_buffer = match bottom - top + 1 {
+            1 => format!("yy -> {}", self.row_to_csv(top)),
+            count => format!("{} rows yanked", count),
+        };
+        self.publish_clipboard();
+    }
+
+    /// Empties the cells of `span` and moves to its first cell, keeping the
+    /// column for whole rows. Returns how many cells held a value.
+    fn clear_span(&mut self, span: Span) -> usize {
+        let writes: Vec<(CellId, String)> = self
+            .cells
+            .keys()
+            .filter(|cell| match span {
+                Span::Rows { top, bottom } => (top..=bottom).contains(&cell.row),
+                Span::Cells { row, left, right } => {
+                    cell.row == row && (left..=right).contains(&cell.col)
+                }
+            })
+            .map(|cell| (*cell, String::new()))
+            .collect();
+        let count = writes.len();
+        self.set_cells(writes);
+        match span {
+            Span::Rows { top, .. } => self.cursor.row = top,
+            Span::Cells { row, left, .. } => {
+                self.cursor.row = row;
+                self.cursor.col = left;
+            }
+        }
+        self.ensure_cursor_visible();
+        count
+    }
+
+    fn row_has_values(&self, row: usize) -> bool {
+        self.cells.keys().any(|cell| cell.row == row)
+    }
+}
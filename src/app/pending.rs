@@ -0,0 +1,164 @@
+//! Keys typed towards a normal-mode command that is not complete yet, such
+//! as the `3` and `d` of `3dd`.
+
+use std::fmt;
+
+/// Largest count accepted, so a stray run of digits cannot stall the app
+/// repeating a command.
+const MAX_COUNT: usize = 99_999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operator {
+    Delete,
+    Yank,
+    /// Clears the cells and starts editing the first one.
+    Change,
+}
+
+impl Operator {
+    pub(crate) fn key(self) -> char {
+        match self {
+            Self::Delete => 'd',
+            Self::Yank => 'y',
+            Self::Change => 'c',
+        }
+    }
+}
+
+/// A key that only means something together with the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Prefix {
+    /// `g`, for `gg`, `gt` and `gT`.
+    Go,
+    /// `z`, for the column commands `zh`, `zl` and `zd`.
+    Column,
+    /// `"`, waiting for a register name.
+    Register,
+    /// `q`, waiting for the register to record a macro into.
+    Record,
+    /// `@`, waiting for the register of the macro to play.
+    Macro,
+}
+
+impl Prefix {
+    fn key(self) -> char {
+        match self {
+            Self::Go => 'g',
+            Self::Column => 'z',
+            Self::Register => '"',
+            Self::Record => 'q',
+            Self::Macro => '@',
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Pending {
+    count: Option<usize>,
+    register: Option<char>,
+    /// The operator and the count typed before it, as in the `2` of `2d3j`.
+    operator: Option<(Operator, Option<usize>)>,
+    prefix: Option<Prefix>,
+}
+
+impl Pending {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.count.is_none()
+            && self.register.is_none()
+            && self.operator.is_none()
+            && self.prefix.is_none()
+    }
+
+    /// Whether `key` goes on the count. A leading `0` does not, so it stays
+    /// free to be a command of its own.
+    pub(crate) fn takes_digit(&self, key: char) -> bool {
+        key.is_ascii_digit() && (key != '0' || self.count.is_some()) && self.prefix.is_none()
+    }
+
+    pub(crate) fn push_digit(&mut self, key: char) {
+        let digit = key.to_digit(10).unwrap_or(0) as usize;
+        let count = self.count.unwrap_or(0).saturating_mul(10) + digit;
+        self.count = Some(count.min(MAX_COUNT));
+    }
+
+    /// Whether a count was typed, for commands such as `G` that mean
+    /// something else without one.
+    pub(crate) fn has_count(&self) -> bool {
+        self.count.is_some() || self.operator.is_some_and(|(_, count)| count.is_some())
+    }
+
+    /// The count to repeat by, 1 when none was typed. Counts before and
+    /// after an operator multiply.
+    pub(crate) fn count(&self) -> usize {
+        let before = self.operator.and_then(|(_, count)| count).unwrap_or(1);
+        before
+            .saturating_mul(self.count.unwrap_or(1))
+            .min(MAX_COUNT)
+    }
+
+    pub(crate) fn register(&self) -> Option<char> {
+        self.register
+    }
+
+    pub(crate) fn operator(&self) -> Option<Operator> {
+        self.operator.map(|(operator, _)| operator)
+    }
+
+    pub(crate) fn prefix(&self) -> Option<Prefix> {
+        self.prefix
+    }
+
+    pub(crate) fn with_prefix(self, prefix: Prefix) -> Self {
+        Self {
+            prefix: Some(prefix),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_register(self, register: char) -> Self {
+        Self {
+            register: Some(register),
+            prefix: None,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_operator(self, operator: Operator) -> Self {
+        Self {
+            operator: Some((operator, self.count)),
+            count: None,
+            ..self
+        }
+    }
+
+    /// The same command with `count` in place of whatever was typed.
+    pub(crate) fn with_count(self, count: usize) -> Self {
+        Self {
+            count: Some(count.min(MAX_COUNT)),
+            operator: self.operator.map(|(operator, _)| (operator, None)),
+            ..self
+        }
+    }
+}
+
+/// Shows the keys typed so far, like vim's `showcmd`.
+impl fmt::Display for Pending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(register) = self.register {
+            write!(f, "\"{}", register)?;
+        }
+        if let Some((operator, count)) = self.operator {
+            if let Some(count) = count {
+                write!(f, "{}", count)?;
+            }
+            write!(f, "{}", operator.key())?;
+        }
+        if let Some(count) = self.count {
+            write!(f, "{}", count)?;
+        }
+        if let Some(prefix) = self.prefix {
+            write!(f, "{}", prefix.key())?;
+        }
+        Ok(())
+    }
+}
@@ -3,12 +3,12 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Style, Stylize},
     text::{Line, Span, Text},
-    widgets::{Block, Paragraph},
+    widgets::{Block, Padding, Paragraph, Tabs},
 };
 
 use crate::app::Mode;
 
-use super::App;
+use super::{App, CellId, formula, value::Value};
 
 const ROW_HEADER_WIDTH: u16 = 5;
 
@@ -24,7 +24,10 @@ impl App {
 
         let desired_footer_lines: u16 = 2;
         let base_footer_height = desired_footer_lines.min(total_area.height);
-        let max_grid_height = total_area.height.saturating_sub(base_footer_height);
+        let tabs_height: u16 = 1.min(total_area.height - base_footer_height);
+        let max_grid_height = total_area
+            .height
+            .saturating_sub(base_footer_height + tabs_height);
         let cell_height: u16 = 1;
         let title_height: u16 = 1;
         let header_height: u16 = cell_height;
@@ -35,21 +38,24 @@ impl App {
                 .min(max_grid_height);
         let footer_carry = total_area
             .height
-            .saturating_sub(grid_height_used + base_footer_height);
+            .saturating_sub(grid_height_used + tabs_height + base_footer_height);
         let footer_height = base_footer_height + footer_carry;
 
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
                 Constraint::Length(grid_height_used),
+                Constraint::Length(tabs_height),
                 Constraint::Length(footer_height),
             ])
             .split(total_area);
 
         let grid_area = layout[0];
-        let footer_area = layout[1];
+        let tabs_area = layout[1];
+        let footer_area = layout[2];
 
         self.visible_rows = rows_to_render;
+        self.resize_grid(total_area.width);
 
         if grid_area.height > 0
             && grid_area.width > 0
@@ -59,6 +65,10 @@ impl App {
             self.render_grid(frame, grid_area, rows_to_render, cell_height);
         }
 
+        if tabs_area.height > 0 {
+            self.render_sheet_tabs(frame, tabs_area);
+        }
+
         if footer_area.height > 0 {
             self.render_footer(
                 frame,
@@ -69,6 +79,13 @@ impl App {
         }
     }
 
+    /// Recomputes how many columns fit in a terminal `width` cells wide and
+    /// scrolls so the cursor stays on screen.
+    pub(super) fn resize_grid(&mut self, width: u16) {
+        self.grid_width = width.saturating_sub(ROW_HEADER_WIDTH);
+        self.ensure_cursor_visible();
+    }
+
     fn render_grid(
         &mut self,
         frame: &mut Frame,
@@ -108,8 +125,10 @@ impl App {
 
         let mut row_constraints = Vec::with_capacity(rows_to_render + 1);
         row_constraints.push(Constraint::Length(header_height));
-        row_constraints
-            .extend(std::iter::repeat(Constraint::Length(cell_height)).take(rows_to_render));
+        row_constraints.extend(std::iter::repeat_n(
+            Constraint::Length(cell_height),
+            rows_to_render,
+        ));
         let grid_rows = Layout::default()
             .direction(Direction::Vertical)
             .constraints(row_constraints)
@@ -128,17 +147,23 @@ impl App {
         }
     }
 
+    /// One tab per sheet, the active one highlighted.
+    fn render_sheet_tabs(&self, frame: &mut Frame, area: Rect) {
+        let titles = self.sheets.iter().map(|sheet| sheet.name.clone());
+        let tabs = Tabs::new(titles)
+            .select(self.active_sheet)
+            .style(self.header_style(false))
+            .highlight_style(self.header_style(true).bold())
+            .divider("|");
+        frame.render_widget(tabs, area);
+    }
+
     fn render_column_headers(&self, frame: &mut Frame, area: Rect, row_header_width: u16) {
         if area.height == 0 || area.width == 0 {
             return;
         }
 
-        let constraints: Vec<Constraint> = std::iter::once(Constraint::Length(row_header_width))
-            .chain(
-                std::iter::repeat(Constraint::Ratio(1, self.visible_cols.max(1) as u32))
-                    .take(self.visible_cols),
-            )
-            .collect();
+        let constraints = self.column_constraints(row_header_width);
 
         let header_chunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -160,7 +185,12 @@ impl App {
             .block(Block::default().style(corner_style));
         frame.render_widget(corner_widget, header_chunks[0]);
 
-        for (idx, chunk) in header_chunks.iter().enumerate().skip(1) {
+        for (idx, chunk) in header_chunks
+            .iter()
+            .enumerate()
+            .take(self.visible_cols + 1)
+            .skip(1)
+        {
             let global_col = self.viewport.col + idx - 1;
             let label = column_name(global_col);
             let selected = global_col == self.cursor.col;
@@ -183,12 +213,7 @@ impl App {
             return;
         }
 
-        let constraints: Vec<Constraint> = std::iter::once(Constraint::Length(row_header_width))
-            .chain(
-                std::iter::repeat(Constraint::Ratio(1, self.visible_cols.max(1) as u32))
-                    .take(self.visible_cols),
-            )
-            .collect();
+        let constraints = self.column_constraints(row_header_width);
 
         let col_chunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -199,6 +224,7 @@ impl App {
             return;
         }
 
+        let selection = self.selection();
         let row_label = (global_row + 1).to_string();
         let row_selected = global_row == self.cursor.row;
         let row_style = self.header_style(row_selected);
@@ -207,12 +233,25 @@ impl App {
             .block(Block::default().style(row_style));
         frame.render_widget(row_widget, col_chunks[0]);
 
-        for (idx, cell_area) in col_chunks.iter().enumerate().skip(1) {
+        for (idx, cell_area) in col_chunks
+            .iter()
+            .enumerate()
+            .take(self.visible_cols + 1)
+            .skip(1)
+        {
             let global_col = self.viewport.col + idx - 1;
             let style = if global_row == self.cursor.row && global_col == self.cursor.col {
                 Style::default()
                     .bg(self.theme.selected_cell_bg)
                     .fg(self.theme.selected_cell_fg)
+            } else if selection.is_some_and(|sel| sel.contains(global_row, global_col)) {
+                Style::default()
+                    .bg(self.theme.selection_bg)
+                    .fg(self.theme.selection_fg)
+            } else if self.is_highlighted_match(CellId::new(global_row, global_col)) {
+                Style::default()
+                    .bg(self.theme.search_match_bg)
+                    .fg(self.theme.search_match_fg)
             } else if global_row == self.cursor.row {
                 Style::default()
                     .bg(self.theme.selected_row_bg)
@@ -226,16 +265,42 @@ impl App {
             };
 
             let display = self.render_cell_text(global_row, global_col);
+            let editing = matches!(self.mode, Mode::Insert(_))
+                && global_row == self.cursor.row
+                && global_col == self.cursor.col;
+            let alignment = match self.cell_value(CellId::new(global_row, global_col)) {
+                _ if editing => Alignment::Left,
+                value if value.is_numeric() => Alignment::Right,
+                Value::Bool(_) | Value::Error(_) => Alignment::Center,
+                _ => Alignment::Left,
+            };
 
-            let cell_block = Block::default().style(style);
+            // Keep right-aligned values off the next column's text.
+            let padding = match alignment {
+                Alignment::Right => Padding::right(1),
+                _ => Padding::ZERO,
+            };
+            let cell_block = Block::default().style(style).padding(padding);
             let cell_widget = Paragraph::new(display)
-                .alignment(Alignment::Left)
+                .alignment(alignment)
                 .block(cell_block);
 
             frame.render_widget(cell_widget, *cell_area);
         }
     }
 
+    /// The row header followed by each visible column at its own width,
+    /// with whatever is left over kept blank at the right edge.
+    fn column_constraints(&self, row_header_width: u16) -> Vec<Constraint> {
+        std::iter::once(Constraint::Length(row_header_width))
+            .chain(
+                (self.viewport.col..self.viewport.col + self.visible_cols)
+                    .map(|col| Constraint::Length(self.column_width(col))),
+            )
+            .chain(std::iter::once(Constraint::Min(0)))
+            .collect()
+    }
+
     fn render_footer(&self, frame: &mut Frame, area: Rect, base_lines: usize, carry_lines: usize) {
         if area.height == 0 {
             return;
@@ -250,11 +315,31 @@ impl App {
 
         if base_lines > 0 && !footer_chunks.is_empty() {
             let cell_label = format!("{}{}", column_name(self.cursor.col), self.cursor.row + 1);
-            let line;
-            if self.file_name.is_empty() {
-                line = format!("[No Name] - [{}]", cell_label);
+            let mut line = if self.file_name.is_empty() {
+                format!("[No Name] - [{}]", cell_label)
             } else {
-                line = format!("{} - [{}]", self.file_name, cell_label);
+                format!("{} - [{}]", self.file_name, cell_label)
+            };
+            if let Some(chain) = self.cycles.first() {
+                let labels: Vec<String> = chain
+                    .iter()
+                    .map(|node| {
+                        let cell = format!("{}{}", column_name(node.cell.col), node.cell.row + 1);
+                        match self.name_of_sheet(node.sheet) {
+                            Some(name) if node.sheet != self.sheet_id() => {
+                                format!("{}{}", formula::sheet_prefix(name), cell)
+                            }
+                            _ => cell,
+                        }
+                    })
+                    .collect();
+                line.push_str(&format!(" - circular reference: {}", labels.join(" -> ")));
+                if self.cycles.len() > 1 {
+                    line.push_str(&format!(" (+{} more)", self.cycles.len() - 1));
+                }
+            }
+            if let Some(register) = self.macros.recording() {
+                line.push_str(&format!(" - recording @{}", register));
             }
             frame.render_widget(
                 Paragraph::new(line).style(self.global_style()),
@@ -263,12 +348,18 @@ impl App {
         }
 
         if base_lines > 1 && footer_chunks.len() > 1 {
-            let mode_line;
-            match self.mode {
-                Mode::Insert(_) => mode_line = format!("-- INSERT -- "),
-                Mode::Command => mode_line = format!(":{}", self.command_buffer),
-                Mode::Normal => mode_line = format!("{}", self.command_buffer),
-            }
+            let mode_line = match self.mode {
+                Mode::Insert(_) => "-- INSERT -- ".to_string(),
+                Mode::Visual(_) if !self.pending.is_empty() => {
+                    format!("-- {} -- {}", self.mode, self.pending)
+                }
+                Mode::Visual(_) => format!("-- {} -- {}", self.mode, self.command_buffer),
+                Mode::Command => format!(":{}", self.command_buffer),
+                Mode::Search(direction) => format!("{}{}", direction.prompt(), self.command_buffer),
+                Mode::Confirm => self.command_buffer.clone(),
+                Mode::Normal if !self.pending.is_empty() => self.pending.to_string(),
+                Mode::Normal => self.command_buffer.clone(),
+            };
             frame.render_widget(
                 Paragraph::new(mode_line).style(self.global_style()),
                 footer_chunks[1],
@@ -284,7 +375,7 @@ impl App {
         let value;
         let cursor = match self.mode {
             Mode::Insert(state) if self.cursor.row == row && self.cursor.col == col => {
-                value = self.get_cell_value(row, col);
+                value = self.editing.clone();
                 Some(state.cursor)
             }
             _ => {
@@ -299,7 +390,7 @@ impl App {
             let cursor_style = Style::default()
                 .fg(self.theme.cursor_fg)
                 .bg(self.theme.cursor_bg);
-            if almost_after.len() < 1 {
+            if almost_after.is_empty() {
                 return Text::from(Line::from(vec![
                     Span::raw(value),
                     Span::styled(" ", cursor_style),
@@ -319,26 +410,11 @@ impl App {
         }
     }
 
-    fn get_cell_value(&self, row: usize, col: usize) -> String {
-        match self.cells.get(&super::CellId::new(row, col)) {
-            Some(value) if !value.is_empty() => value.clone(),
-            _ => "".to_string(),
-        }
-    }
-
-    fn get_cell_display_text(&self, row: usize, col: usize) -> String {
-        let value = self.get_cell_value(row, col);
-
-        if value.starts_with("=") {
-            let (_, formula) = value.split_at(1);
-
-            return match formula.to_uppercase().as_str() {
-                "SUM" => "1".to_string(),
-                _ => "#NAME?".to_string(),
-            };
-        }
-
-        return value;
+    /// The text a cell shows outside insert mode: its typed or computed
+    /// value in the cell's number format rather than what was typed.
+    pub(super) fn get_cell_display_text(&self, row: usize, col: usize) -> String {
+        let cell = CellId::new(row, col);
+        self.format_value(cell, &self.cell_value(cell))
     }
 
     fn header_style(&self, selected: bool) -> Style {
@@ -360,7 +436,7 @@ impl App {
     }
 }
 
-fn column_name(mut index: usize) -> String {
+pub(crate) fn column_name(mut index: usize) -> String {
     let mut name = String::new();
     index += 1;
     while index > 0 {
@@ -371,7 +447,7 @@ fn column_name(mut index: usize) -> String {
     name
 }
 
-fn _column_index(name: &str) -> usize {
+pub(crate) fn column_index(name: &str) -> usize {
     let mut index = 0;
 
     for c in name.chars() {
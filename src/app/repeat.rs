@@ -0,0 +1,334 @@
+//! `.`, which repeats the last change at the cursor. Changes are kept as the
+//! commands that made them rather than the cells they wrote, so a repeat
+//! acts on whatever sits under the cursor now.
+
+use super::{App, Cursor, Mode, VisualKind, VisualState, motion::Motion, pending::Pending};
+
+#[derive(Debug, Clone)]
+pub(crate) enum Change {
+    /// An operator over a motion, as in `dj`, or over rows when `motion` is
+    /// `None`, as in `3dd`.
+    Operator {
+        pending: Pending,
+        motion: Option<Motion>,
+    },
+    /// `p` or `P`.
+    Paste { after: bool, count: usize },
+    /// `zh` or `zl`.
+    InsertColumns { right: bool, count: usize },
+    /// `zd`.
+    DeleteColumns { count: usize },
+    /// A change to a visual selection, kept as the size of the selection so
+    /// a repeat covers as many cells from the cursor.
+    Visual {
+        kind: VisualKind,
+        rows: usize,
+        cols: usize,
+        action: VisualAction,
+    },
+    /// An insert session: the command that began it and the keys typed in
+    /// it. `enter` is set when it was left with Enter rather than Esc.
+    /// `count` is how many times the keys are typed, or for `o` and `O` how
+    /// many rows are opened.
+    Insert {
+        start: InsertStart,
+        edits: Vec<Edit>,
+        enter: bool,
+        count: usize,
+    },
+}
+
+impl Change {
+    /// The change with its count replaced, as `3.` does in vim.
+    fn with_count(self, count: usize) -> Self {
+        match self {
+            Self::Operator { pending, motion } => Self::Operator {
+                pending: pending.with_count(count),
+                motion,
+            },
+            Self::Paste { after, .. } => Self::Paste { after, count },
+            Self::InsertColumns { right, .. } => Self::InsertColumns { right, count },
+            Self::DeleteColumns { .. } => Self::DeleteColumns { count },
+            Self::Insert {
+                start: InsertStart::Operator { pending, motion },
+                edits,
+                enter,
+                ..
+            } => Self::Insert {
+                start: InsertStart::Operator {
+                    pending: pending.with_count(count),
+                    motion,
+                },
+                edits,
+                enter,
+                count: 1,
+            },
+            Self::Insert {
+                start,
+                edits,
+                enter,
+                ..
+            } => Self::Insert {
+                start,
+                edits,
+                enter,
+                count,
+            },
+            change @ Self::Visual { .. } => change,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VisualAction {
+    Delete,
+    Paste,
+    FillDown,
+    FillRight,
+}
+
+/// The command that began an insert session.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum InsertStart {
+    /// `i`.
+    Start,
+    /// `a`.
+    End,
+    /// `o`.
+    RowBelow,
+    /// `O`.
+    RowAbove,
+    /// `c` with a motion, or `cc`.
+    Operator {
+        pending: Pending,
+        motion: Option<Motion>,
+    },
+}
+
+/// A key typed in insert mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Edit {
+    Char(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    CycleAnchor,
+}
+
+/// The insert session being typed, recorded for `.`.
+#[derive(Debug)]
+pub(crate) struct InsertSession {
+    start: InsertStart,
+    edits: Vec<Edit>,
+}
+
+impl App {
+    /// Makes `change` and remembers it for `.`. Insert sessions are only
+    /// remembered once they end, and pastes only once there was something
+    /// to paste.
+    pub(super) fn perform(&mut self, change: Change) {
+        match change {
+            Change::Operator { pending, motion } => {
+                self.run_operator(pending, motion);
+                self.last_change = Some(change);
+            }
+            Change::Paste { after, count } => {
+                if self.paste_clipboard(after, count) {
+                    self.last_change = Some(change);
+                }
+            }
+            Change::InsertColumns { right, count } => {
+                self.insert_columns_at_cursor(right, count);
+                self.last_change = Some(change);
+            }
+            Change::DeleteColumns { count } => {
+                self.delete_columns_at_cursor(count);
+                self.last_change = Some(change);
+            }
+            Change::Visual {
+                kind,
+                rows,
+                cols,
+                action,
+            } => {
+                self.select_from_cursor(kind, rows, cols);
+                let ran = match action {
+                    VisualAction::Delete => {
+                        self.delete_selection();
+                        true
+                    }
+                    VisualAction::Paste => self.paste_into_selection(),
+                    VisualAction::FillDown => {
+                        self.fill_selection_down();
+                        true
+                    }
+                    VisualAction::FillRight => {
+                        self.fill_selection_right();
+                        true
+                    }
+                };
+                if ran {
+                    self.last_change = Some(change);
+                }
+            }
+            Change::Insert {
+                start,
+                ref edits,
+                enter,
+                count,
+            } => {
+                let (sessions, times) = match start {
+                    InsertStart::RowBelow | InsertStart::RowAbove => (count, 1),
+                    _ => (1, count),
+                };
+                for _ in 0..sessions {
+                    self.begin_insert(start);
+                    if !matches!(self.mode, Mode::Insert(_)) {
+                        return;
+                    }
+                    for _ in 0..times {
+                        for &edit in edits {
+                            self.apply_edit(edit);
+                        }
+                    }
+                    self.finish_insert(enter);
+                }
+                self.last_change = Some(change);
+            }
+        }
+    }
+
+    /// `.`: makes the last change again at the cursor, with `count` in
+    /// place of its own when one is given.
+    pub(super) fn repeat_last_change(&mut self, count: Option<usize>) {
+        let Some(change) = self.last_change.clone() else {
+            return;
+        };
+        self.perform(match count {
+            Some(count) => change.with_count(count),
+            None => change,
+        });
+    }
+
+    /// Applies `action` to the visual selection and remembers it for `.`.
+    pub(super) fn change_selection(&mut self, action: VisualAction) {
+        let Mode::Visual(state) = self.mode else {
+            return;
+        };
+        let (anchor, cursor) = (state.anchor, self.cursor);
+        self.cursor = Cursor {
+            row: anchor.row.min(cursor.row),
+            col: anchor.col.min(cursor.col),
+        };
+        self.perform(Change::Visual {
+            kind: state.kind,
+            rows: anchor.row.abs_diff(cursor.row),
+            cols: anchor.col.abs_diff(cursor.col),
+            action,
+        });
+    }
+
+    /// Selects `rows` by `cols` cells more than the cursor's own, down and
+    /// to the right of it.
+    fn select_from_cursor(&mut self, kind: VisualKind, rows: usize, cols: usize) {
+        self.mode = Mode::Visual(VisualState {
+            anchor: self.cursor,
+            kind,
+        });
+        self.cursor.row += rows;
+        self.cursor.col += cols;
+    }
+
+    /// Starts recording the insert session `start` just began.
+    pub(super) fn record_insert(&mut self, start: InsertStart) {
+        if matches!(self.mode, Mode::Insert(_)) {
+            self.insert_session = Some(InsertSession {
+                start,
+                edits: Vec::new(),
+            });
+        }
+    }
+
+    pub(super) fn record_edit(&mut self, edit: Edit) {
+        if let Some(session) = &mut self.insert_session {
+            session.edits.push(edit);
+        }
+    }
+
+    /// Leaves insert mode, moving down a row after Enter, and remembers the
+    /// session for `.`.
+    pub(super) fn finish_insert(&mut self, enter: bool) {
+        self.enter_normal_mode();
+        if enter {
+            self.move_cursor(0, 1);
+        }
+        if let Some(session) = self.insert_session.take() {
+            self.last_change = Some(Change::Insert {
+                start: session.start,
+                edits: session.edits,
+                enter,
+                count: 1,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::super::{App, CellId, Mode, value::Value};
+    use super::{Change, VisualAction};
+
+    fn press(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            app.on_key_event(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn counts_repeat_insert_sessions() {
+        let mut app = App::new();
+        press(&mut app, "ix");
+        app.on_key_event(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        press(&mut app, "j3.");
+        assert_eq!(app.cell_value(CellId::new(1, 0)), Value::Text("xxx".into()));
+        press(&mut app, "j.");
+        assert_eq!(app.cell_value(CellId::new(2, 0)), Value::Text("xxx".into()));
+        press(&mut app, "u");
+        assert_eq!(app.cell_value(CellId::new(2, 0)), Value::Empty);
+
+        let mut app = App::new();
+        press(&mut app, "oy");
+        app.on_key_event(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        press(&mut app, "2.");
+        for row in 1..4 {
+            assert_eq!(app.cell_value(CellId::new(row, 0)), Value::Text("y".into()));
+        }
+    }
+
+    #[test]
+    fn visual_paste_of_nothing_leaves_visual_mode_and_is_not_repeated() {
+        let mut app = App::new();
+        app.set_cell(CellId::new(0, 0), "5".to_string());
+        press(&mut app, "vj");
+        app.on_key_event(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL));
+
+        press(&mut app, "vlp");
+        assert!(matches!(app.mode, Mode::Normal));
+        assert!(matches!(
+            app.last_change,
+            Some(Change::Visual {
+                action: VisualAction::FillDown,
+                ..
+            })
+        ));
+
+        press(&mut app, "p");
+        assert!(matches!(app.last_change, Some(Change::Visual { .. })));
+        press(&mut app, ".");
+        assert!(matches!(app.mode, Mode::Normal));
+        assert_eq!(app.cell_value(CellId::new(1, 0)), Value::Number(5.0));
+    }
+}
@@ -0,0 +1,187 @@
+//! `/` and `?` searches over cell contents.
+
+use super::{App, CellId, Mode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+impl SearchDirection {
+    pub(crate) fn prompt(self) -> char {
+        match self {
+            Self::Forward => '/',
+            Self::Backward => '?',
+        }
+    }
+
+    fn reversed(self) -> Self {
+        match self {
+            Self::Forward => Self::Backward,
+            Self::Backward => Self::Forward,
+        }
+    }
+}
+
+/// What a search looks at besides the raw contents of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchScope {
+    Raw,
+    /// Also matches the value a cell shows, such as a formula's result.
+    Displayed,
+}
+
+impl SearchScope {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Self::Raw),
+            "displayed" | "display" => Some(Self::Displayed),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Displayed => "displayed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Search {
+    pattern: String,
+    direction: SearchDirection,
+    /// Cleared by `:noh` until the next search.
+    highlight: bool,
+}
+
+impl Search {
+    pub(crate) fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl App {
+    pub(super) fn enter_search_mode(&mut self, direction: SearchDirection) {
+        self.mode = Mode::Search(direction);
+        self.command_buffer.clear();
+    }
+
+    /// Runs the search typed after `/` or `?`. An empty pattern repeats the
+    /// last one in the new direction.
+    pub(super) fn start_search(&mut self, direction: SearchDirection) {
+        let pattern = match (self.command_buffer.as_str(), &self.search) {
+            ("", Some(search)) => search.pattern.clone(),
+            ("", None) => {
+                self.command_buffer = "E35: No previous search pattern".to_string();
+                return;
+            }
+            (pattern, _) => pattern.to_string(),
+        };
+        self.search = Some(Search {
+            pattern,
+            direction,
+            highlight: true,
+        });
+        self.search_next(false);
+    }
+
+    /// Jumps to the next match in row-major order, `n`, or to the previous
+    /// one with `reverse`, `N`. Wraps around the ends of the sheet.
+    pub(super) fn search_next(&mut self, reverse: bool) {
+        let Some(search) = &mut self.search else {
+            self.command_buffer = "E35: No previous search pattern".to_string();
+            return;
+        };
+        search.highlight = true;
+        let direction = if reverse {
+            search.direction.reversed()
+        } else {
+            search.direction
+        };
+        let pattern = search.pattern.clone();
+
+        let mut matches: Vec<CellId> = self
+            .cells
+            .keys()
+            .filter(|cell| self.is_search_match(**cell))
+            .copied()
+            .collect();
+        if matches.is_empty() {
+            self.command_buffer = format!("E486: Pattern not found: {}", pattern);
+            return;
+        }
+        matches.sort_by_key(|cell| (cell.row, cell.col));
+
+        let here = (self.cursor.row, self.cursor.col);
+        let (index, wrapped) = match direction {
+            SearchDirection::Forward => {
+                match matches.iter().position(|cell| (cell.row, cell.col) > here) {
+                    Some(index) => (index, false),
+                    None => (0, true),
+                }
+            }
+            SearchDirection::Backward => {
+                match matches.iter().rposition(|cell| (cell.row, cell.col) < here) {
+                    Some(index) => (index, false),
+                    None => (matches.len() - 1, true),
+                }
+            }
+        };
+        let target = matches[index];
+        self.move_cursor(
+            target.col as i32 - self.cursor.col as i32,
+            target.row as i32 - self.cursor.row as i32,
+        );
+
+        self.command_buffer = match (wrapped, direction) {
+            (false, _) => format!(
+                "{}{} [{}/{}]",
+                direction.prompt(),
+                pattern,
+                index + 1,
+                matches.len()
+            ),
+            (true, SearchDirection::Forward) => "search hit BOTTOM, continuing at TOP".to_string(),
+            (true, SearchDirection::Backward) => "search hit TOP, continuing at BOTTOM".to_string(),
+        };
+    }
+
+    /// Hides match highlighting until the next search, `:noh`.
+    pub(super) fn clear_search_highlight(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.highlight = false;
+        }
+    }
+
+    /// Whether `cell` should be drawn as a search match.
+    pub(super) fn is_highlighted_match(&self, cell: CellId) -> bool {
+        self.search
+            .as_ref()
+            .is_some_and(|search| search.highlight && self.is_search_match(cell))
+    }
+
+    /// Whether `cell` matches the current search. Patterns are matched as
+    /// plain text, ignoring case unless they contain an uppercase letter.
+    fn is_search_match(&self, cell: CellId) -> bool {
+        let Some(search) = &self.search else {
+            return false;
+        };
+        let Some(raw) = self.cells.get(&cell) else {
+            return false;
+        };
+        let ignore_case = !search.pattern.chars().any(char::is_uppercase);
+        let found = |text: &str| {
+            if ignore_case {
+                text.to_lowercase().contains(&search.pattern)
+            } else {
+                text.contains(&search.pattern)
+            }
+        };
+        found(raw)
+            || (self.search_scope == SearchScope::Displayed
+                && found(&self.get_cell_display_text(cell.row, cell.col)))
+    }
+}
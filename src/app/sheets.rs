@@ -0,0 +1,425 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use super::{
+    App, CellId, Cursor, Viewport,
+    calc::Values,
+    format::NumberFormat,
+    formula,
+    history::{CellChange, History},
+    visual::Selection,
+};
+
+/// Names a sheet for as long as it exists, through renames and moves, so
+/// the dependency graph can point into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct SheetId(u32);
+
+impl SheetId {
+    fn next() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Everything `App` keeps per sheet. The active sheet lives in `App`'s own
+/// fields while it is shown; the others are parked here.
+#[derive(Debug)]
+pub(crate) struct Sheet {
+    pub(crate) id: SheetId,
+    pub(crate) name: String,
+    pub(crate) cells: HashMap<CellId, String>,
+    pub(crate) column_widths: HashMap<usize, u16>,
+    pub(crate) formats: Vec<(Selection, NumberFormat)>,
+    pub(crate) computed: Values,
+    pub(crate) cursor: Cursor,
+    viewport: Viewport,
+    history: History,
+}
+
+impl Sheet {
+    pub(crate) fn named(name: impl Into<String>) -> Self {
+        Self {
+            id: SheetId::next(),
+            name: name.into(),
+            cells: HashMap::new(),
+            column_widths: HashMap::new(),
+            formats: Vec::new(),
+            computed: Values::default(),
+            cursor: Cursor::default(),
+            viewport: Viewport::default(),
+            history: History::default(),
+        }
+    }
+}
+
+impl App {
+    pub(super) fn sheet_name(&self) -> &str {
+        &self.sheets[self.active_sheet].name
+    }
+
+    pub(super) fn sheet_id(&self) -> SheetId {
+        self.sheets[self.active_sheet].id
+    }
+
+    /// The id of the sheet called `name`, ignoring case.
+    pub(super) fn sheet_named(&self, name: &str) -> Option<SheetId> {
+        self.sheet_index(name).map(|index| self.sheets[index].id)
+    }
+
+    fn sheet_position(&self, sheet: SheetId) -> Option<usize> {
+        self.sheets.iter().position(|slot| slot.id == sheet)
+    }
+
+    pub(super) fn name_of_sheet(&self, sheet: SheetId) -> Option<&str> {
+        let index = self.sheet_position(sheet)?;
+        Some(&self.sheets[index].name)
+    }
+
+    /// The raw cells of `sheet`, whether it is active or parked.
+    pub(super) fn cells_of(&self, sheet: SheetId) -> Option<&HashMap<CellId, String>> {
+        match self.sheet_position(sheet)? {
+            index if index == self.active_sheet => Some(&self.cells),
+            index => Some(&self.sheets[index].cells),
+        }
+    }
+
+    /// The computed values of `sheet`, whether it is active or parked.
+    pub(super) fn values_of(&self, sheet: SheetId) -> Option<&Values> {
+        match self.sheet_position(sheet)? {
+            index if index == self.active_sheet => Some(&self.computed),
+            index => Some(&self.sheets[index].computed),
+        }
+    }
+
+    pub(super) fn values_of_mut(&mut self, sheet: SheetId) -> Option<&mut Values> {
+        match self.sheet_position(sheet)? {
+            index if index == self.active_sheet => Some(&mut self.computed),
+            index => Some(&mut self.sheets[index].computed),
+        }
+    }
+
+    /// Moves the active sheet out of `App`'s fields into its slot.
+    pub(super) fn park_sheet(&mut self) {
+        let slot = &mut self.sheets[self.active_sheet];
+        slot.cells = std::mem::take(&mut self.cells);
+        slot.column_widths = std::mem::take(&mut self.column_widths);
+        slot.formats = std::mem::take(&mut self.formats);
+        slot.computed = std::mem::take(&mut self.computed);
+        slot.cursor = self.cursor;
+        slot.viewport = self.viewport;
+        slot.history = std::mem::take(&mut self.history);
+    }
+
+    /// Makes sheet `index` the active one. The current one must already be
+    /// parked.
+    pub(super) fn unpark_sheet(&mut self, index: usize) {
+        self.active_sheet = index;
+        let slot = &mut self.sheets[index];
+        self.cells = std::mem::take(&mut slot.cells);
+        self.column_widths = std::mem::take(&mut slot.column_widths);
+        self.formats = std::mem::take(&mut slot.formats);
+        self.computed = std::mem::take(&mut slot.computed);
+        self.cursor = slot.cursor;
+        self.viewport = slot.viewport;
+        self.history = std::mem::take(&mut slot.history);
+    }
+
+    /// Runs `f` with each sheet in turn made active, then goes back to the
+    /// sheet that was active before.
+    pub(super) fn for_each_sheet(&mut self, mut f: impl FnMut(&mut App)) {
+        let active = self.active_sheet;
+        self.park_sheet();
+        for index in 0..self.sheets.len() {
+            self.unpark_sheet(index);
+            f(self);
+            self.park_sheet();
+        }
+        self.unpark_sheet(active);
+    }
+
+    /// Runs `f` with each sheet other than the active one made active.
+    pub(super) fn for_each_other_sheet(&mut self, mut f: impl FnMut(&mut App)) {
+        let active = self.active_sheet;
+        self.for_each_sheet(|app| {
+            if app.active_sheet != active {
+                f(app);
+            }
+        });
+    }
+
+    pub(super) fn switch_sheet(&mut self, index: usize) {
+        if index == self.active_sheet || index >= self.sheets.len() {
+            return;
+        }
+        self.park_sheet();
+        self.unpark_sheet(index);
+        self.ensure_cursor_visible();
+        self.command_buffer = format!("sheet {}", self.sheet_name());
+    }
+
+    /// Moves `delta` tabs along, wrapping around at either end.
+    pub(super) fn cycle_sheet(&mut self, delta: isize) {
+        let count = self.sheets.len() as isize;
+        let index = (self.active_sheet as isize + delta).rem_euclid(count);
+        self.switch_sheet(index as usize);
+    }
+
+    /// Handles `:sheet`, `:sheet new [name]`, `:sheet rename <name>`,
+    /// `:sheet delete` and `:sheet <name>`.
+    pub(super) fn handle_sheet_command(&mut self, args: &[&str]) {
+        let result = match args {
+            [] => {
+                let names: Vec<&str> = self
+                    .sheets
+                    .iter()
+                    .map(|sheet| sheet.name.as_str())
+                    .collect();
+                self.command_buffer = format!("sheets: {}", names.join(", "));
+                Ok(())
+            }
+            ["new", name @ ..] => self.add_sheet(&name.join(" ")),
+            ["rename", name @ ..] => self.rename_active_sheet(&name.join(" ")),
+            ["delete"] => self.delete_active_sheet(),
+            name => match self.sheet_index(&name.join(" ")) {
+                Some(index) => {
+                    self.switch_sheet(index);
+                    Ok(())
+                }
+                None => Err(format!("no sheet named {}", name.join(" "))),
+            },
+        };
+        if let Err(err) = result {
+            self.command_buffer = err;
+        }
+    }
+
+    fn sheet_index(&self, name: &str) -> Option<usize> {
+        self.sheets
+            .iter()
+            .position(|sheet| sheet.name.eq_ignore_ascii_case(name))
+    }
+
+    fn add_sheet(&mut self, name: &str) -> Result<(), String> {
+        let name = if name.is_empty() {
+            (1..)
+                .map(|n| format!("Sheet{}", n))
+                .find(|name| self.sheet_index(name).is_none())
+                .expect("some sheet number is free")
+        } else {
+            self.check_sheet_name(name, false)?;
+            name.to_string()
+        };
+        self.park_sheet();
+        let index = self.active_sheet + 1;
+        self.sheets.insert(index, Sheet::named(name));
+        self.unpark_sheet(index);
+        self.modified = true;
+        // References to the new name may have been dangling until now.
+        self.recalculate_all();
+        self.command_buffer = format!("sheet {} added", self.sheet_name());
+        Ok(())
+    }
+
+    /// Renames the active sheet and every reference to it, including those
+    /// kept for undo.
+    fn rename_active_sheet(&mut self, name: &str) -> Result<(), String> {
+        self.check_sheet_name(name, true)?;
+        let old = self.sheet_name().to_string();
+        self.sheets[self.active_sheet].name = name.to_string();
+        self.modified = true;
+        self.for_each_sheet(|app| {
+            let rewrite = |raw: &str| formula::rename_sheet(raw, &old, name);
+            app.rewrite_formulas(rewrite);
+            app.history.rewrite(rewrite);
+        });
+        self.recalculate_all();
+        self.command_buffer = format!("sheet {} renamed to {}", old, name);
+        Ok(())
+    }
+
+    /// Deletes the active sheet. References to it elsewhere become `#REF!`.
+    fn delete_active_sheet(&mut self) -> Result<(), String> {
+        if self.sheets.len() == 1 {
+            return Err("cannot delete the only sheet".to_string());
+        }
+        let name = self.sheet_name().to_string();
+        self.for_each_other_sheet(|app| {
+            let rewrite = |raw: &str| formula::drop_sheet(raw, &name);
+            app.rewrite_formulas(rewrite);
+            app.history.rewrite(rewrite);
+        });
+        self.park_sheet();
+        self.sheets.remove(self.active_sheet);
+        self.unpark_sheet(self.active_sheet.min(self.sheets.len() - 1));
+        self.modified = true;
+        self.recalculate_all();
+        self.command_buffer = format!("sheet {} deleted", name);
+        Ok(())
+    }
+
+    /// Checks a new name for a sheet. When `renaming`, the active sheet may
+    /// keep its own name with different case.
+    fn check_sheet_name(&self, name: &str, renaming: bool) -> Result<(), String> {
+        check_name(name)?;
+        match self.sheet_index(name) {
+            Some(index) if !(renaming && index == self.active_sheet) => {
+                Err(format!("sheet {} already exists", self.sheets[index].name))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Rewrites the formulas of the active sheet in place, outside its own
+    /// undo history, and recomputes whatever changed. Returns the changes so
+    /// the caller can record them where they belong.
+    pub(super) fn rewrite_formulas(&mut self, rewrite: impl Fn(&str) -> String) -> Vec<CellChange> {
+        let mut changes = Vec::new();
+        for (cell, raw) in self.cells.iter_mut() {
+            if formula::formula_body(raw).is_none() {
+                continue;
+            }
+            let rewritten = rewrite(raw);
+            if rewritten != *raw {
+                self.modified = true;
+                changes.push(CellChange {
+                    cell: *cell,
+                    before: Some(std::mem::replace(raw, rewritten.clone())),
+                    after: Some(rewritten),
+                });
+            }
+        }
+        if !changes.is_empty() {
+            let changed: Vec<CellId> = changes.iter().map(|change| change.cell).collect();
+            self.recalculate(&changed);
+        }
+        changes
+    }
+
+    /// Writes cells kept by the undo history back onto other sheets, for
+    /// `before` or `after` depending on `undo`. Sheets deleted since are
+    /// skipped.
+    pub(super) fn restore_elsewhere(
+        &mut self,
+        elsewhere: &[(SheetId, Vec<CellChange>)],
+        undo: bool,
+    ) {
+        self.for_each_other_sheet(|app| {
+            let id = app.sheet_id();
+            for (_, changes) in elsewhere.iter().filter(|(sheet, _)| *sheet == id) {
+                let cells = changes
+                    .iter()
+                    .map(|change| match undo {
+                        true => (change.cell, change.before.clone()),
+                        false => (change.cell, change.after.clone()),
+                    })
+                    .collect();
+                app.restore_cells(cells);
+            }
+        });
+    }
+}
+
+/// Checks a sheet name on its own, without looking at the other sheets.
+pub(super) fn check_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("sheet name cannot be empty".to_string());
+    }
+    if name.contains(['!', '\'', ':']) {
+        return Err("sheet names cannot contain ! ' or :".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        App, CellId,
+        value::{CellError, Value},
+    };
+
+    /// A workbook of `Sheet1` and `Other`, with `Sheet1` active.
+    fn two_sheets() -> App {
+        let mut app = App::new();
+        app.handle_sheet_command(&["new", "Other"]);
+        app.switch_sheet(0);
+        app
+    }
+
+    #[test]
+    fn edits_reach_readers_on_other_sheets() {
+        let mut app = two_sheets();
+        app.switch_sheet(1);
+        app.set_cell(CellId::new(0, 0), "=Sheet1!A1*10".to_string());
+        app.switch_sheet(0);
+        app.set_cell(CellId::new(1, 0), "=Other!A1+1".to_string());
+
+        app.set_cell(CellId::new(0, 0), "3".to_string());
+        assert_eq!(app.cell_value(CellId::new(1, 0)), Value::Number(31.0));
+        app.switch_sheet(1);
+        assert_eq!(app.cell_value(CellId::new(0, 0)), Value::Number(30.0));
+    }
+
+    #[test]
+    fn cycles_across_sheets_are_found() {
+        let mut app = two_sheets();
+        app.set_cell(CellId::new(0, 0), "=Other!A1".to_string());
+        app.switch_sheet(1);
+        app.set_cell(CellId::new(0, 0), "=Sheet1!A1".to_string());
+
+        let cycle = Value::Error(CellError::Cycle);
+        assert_eq!(app.cell_value(CellId::new(0, 0)), cycle);
+        assert_eq!(app.cycles.len(), 1);
+        app.switch_sheet(0);
+        assert_eq!(app.cell_value(CellId::new(0, 0)), cycle);
+
+        app.set_cell(CellId::new(0, 0), "5".to_string());
+        assert!(app.cycles.is_empty());
+        app.switch_sheet(1);
+        assert_eq!(app.cell_value(CellId::new(0, 0)), Value::Number(5.0));
+    }
+
+    #[test]
+    fn adding_a_sheet_resolves_dangling_references() {
+        let mut app = App::new();
+        app.set_cell(CellId::new(0, 0), "=Later!A1+1".to_string());
+        assert!(matches!(app.cell_value(CellId::new(0, 0)), Value::Error(_)));
+
+        app.handle_sheet_command(&["new", "Later"]);
+        app.set_cell(CellId::new(0, 0), "4".to_string());
+        app.switch_sheet(0);
+        assert_eq!(app.cell_value(CellId::new(0, 0)), Value::Number(5.0));
+    }
+
+    #[test]
+    fn undo_after_a_rename_keeps_the_new_name() {
+        let mut app = two_sheets();
+        app.set_cell(CellId::new(0, 0), "2".to_string());
+        app.switch_sheet(1);
+        app.set_cell(CellId::new(0, 0), "=Sheet1!A1".to_string());
+        app.set_cell(CellId::new(0, 0), "=Sheet1!A1*3".to_string());
+        app.switch_sheet(0);
+        app.handle_sheet_command(&["rename", "Data"]);
+
+        app.switch_sheet(1);
+        app.undo();
+        assert_eq!(app.cells[&CellId::new(0, 0)], "=Data!A1");
+        assert_eq!(app.cell_value(CellId::new(0, 0)), Value::Number(2.0));
+        app.redo();
+        assert_eq!(app.cells[&CellId::new(0, 0)], "=Data!A1*3");
+    }
+
+    #[test]
+    fn undo_after_a_delete_keeps_references_broken() {
+        let mut app = two_sheets();
+        app.set_cell(CellId::new(0, 0), "=Other!A1".to_string());
+        app.set_cell(CellId::new(0, 0), "=Other!A1+1".to_string());
+        app.switch_sheet(1);
+        app.handle_sheet_command(&["delete"]);
+
+        app.undo();
+        assert_eq!(app.cells[&CellId::new(0, 0)], "=#REF!");
+    }
+}
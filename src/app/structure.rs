@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use super::{
+    App, CellId,
+    formula::{self, Axis},
+    history::Layout,
+};
+
+impl App {
+    /// Inserts `count` empty rows before row `at`.
+    pub(super) fn insert_rows(&mut self, at: usize, count: usize) {
+        self.insert_lines(Axis::Row, at, count);
+    }
+
+    /// Deletes the rows `first..=last`.
+    pub(super) fn delete_rows(&mut self, first: usize, last: usize) {
+        self.delete_lines(Axis::Row, first, last - first + 1);
+        if self.cursor.row > 0 && !self.row_exists(self.cursor.row) {
+            self.cursor.row = self.cursor.row.saturating_sub(1);
+        }
+        self.ensure_cursor_visible();
+    }
+
+    fn row_exists(&self, row: usize) -> bool {
+        self.cells.keys().any(|cell| cell.row == row)
+    }
+
+    /// Inserts `count` empty columns before column `at`.
+    pub(super) fn insert_columns(&mut self, at: usize, count: usize) {
+        self.insert_lines(Axis::Column, at, count);
+    }
+
+    /// Deletes the columns `first..=last`.
+    pub(super) fn delete_columns(&mut self, first: usize, last: usize) {
+        self.delete_lines(Axis::Column, first, last - first + 1);
+    }
+
+    pub(super) fn layout(&self) -> Layout {
+        Layout {
+            column_widths: self.column_widths.clone(),
+            formats: self.formats.clone(),
+        }
+    }
+
+    /// Puts back a layout kept by the undo history.
+    pub(super) fn restore_layout(&mut self, layout: Layout) {
+        self.modified |= layout != self.layout();
+        self.column_widths = layout.column_widths;
+        self.formats = layout.formats;
+    }
+
+    /// Moves the cells at or past `at` along `axis` by `count` and fixes up
+    /// every formula that points past `at`.
+    fn insert_lines(&mut self, axis: Axis, at: usize, count: usize) {
+        let opened = self.history.begin(self.cursor);
+        let layout = self.layout();
+        self.insert_format_lines(axis, at, count);
+        if axis == Axis::Column {
+            self.insert_column_widths(at, count);
+        }
+        let sheet = self.sheet_name().to_string();
+        self.restructure(
+            |cell| match axis.of(cell) {
+                index if index >= at => Some(axis.with(cell, index + count)),
+                _ => Some(cell),
+            },
+            |raw| formula::insert_lines(raw, axis, at, count, |name| is_local(name, &sheet)),
+        );
+        let mut elsewhere = Vec::new();
+        self.for_each_other_sheet(|app| {
+            let changes = app.rewrite_formulas(|raw| {
+                formula::insert_lines(raw, axis, at, count, |name| names(name, &sheet))
+            });
+            elsewhere.push((app.sheet_id(), changes));
+        });
+        for (other, changes) in elsewhere {
+            self.history.record_elsewhere(other, changes);
+        }
+        self.modified |= layout != self.layout();
+        self.history.record_layout(layout, self.layout());
+        if opened {
+            self.history.commit();
+        }
+    }
+
+    /// Drops the lines `at..at + count` along `axis`, pulls the following
+    /// cells back and turns references into the removed lines into `#REF!`.
+    fn delete_lines(&mut self, axis: Axis, at: usize, count: usize) {
+        let end = at + count;
+        let opened = self.history.begin(self.cursor);
+        let layout = self.layout();
+        self.delete_format_lines(axis, at, count);
+        if axis == Axis::Column {
+            self.delete_column_widths(at, count);
+        }
+        let sheet = self.sheet_name().to_string();
+        self.restructure(
+            |cell| match axis.of(cell) {
+                index if index < at => Some(cell),
+                index if index < end => None,
+                index => Some(axis.with(cell, index - count)),
+            },
+            |raw| formula::delete_lines(raw, axis, at, count, |name| is_local(name, &sheet)),
+        );
+        let mut elsewhere = Vec::new();
+        self.for_each_other_sheet(|app| {
+            let changes = app.rewrite_formulas(|raw| {
+                formula::delete_lines(raw, axis, at, count, |name| names(name, &sheet))
+            });
+            elsewhere.push((app.sheet_id(), changes));
+        });
+        for (other, changes) in elsewhere {
+            self.history.record_elsewhere(other, changes);
+        }
+        self.modified |= layout != self.layout();
+        self.history.record_layout(layout, self.layout());
+        if opened {
+            self.history.commit();
+        }
+    }
+
+    /// Moves every cell through `relocate`, dropping those mapped to `None`,
+    /// rewrites every value through `rewrite`, and applies the difference as
+    /// one undoable edit.
+    fn restructure(
+        &mut self,
+        relocate: impl Fn(CellId) -> Option<CellId>,
+        rewrite: impl Fn(&str) -> String,
+    ) {
+        let mut moved: HashMap<CellId, String> = HashMap::with_capacity(self.cells.len());
+        for (cell, raw) in &self.cells {
+            if let Some(target) = relocate(*cell) {
+                moved.insert(target, rewrite(raw));
+            }
+        }
+
+        let mut writes: Vec<(CellId, String)> = self
+            .cells
+            .keys()
+            .filter(|cell| !moved.contains_key(cell))
+            .map(|cell| (*cell, String::new()))
+            .collect();
+        writes.extend(
+            moved
+                .into_iter()
+                .filter(|(cell, raw)| self.cells.get(cell) != Some(raw)),
+        );
+        self.set_cells(writes);
+    }
+}
+
+/// Whether a reference written in a formula on `sheet` points into it:
+/// either unqualified or naming it.
+fn is_local(name: Option<&str>, sheet: &str) -> bool {
+    name.is_none_or(|name| name.eq_ignore_ascii_case(sheet))
+}
+
+/// Whether a reference written on another sheet names `sheet`.
+fn names(name: Option<&str>, sheet: &str) -> bool {
+    name.is_some_and(|name| name.eq_ignore_ascii_case(sheet))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{App, CellId, format::NumberFormat, value::Value, visual::Selection};
+
+    #[test]
+    fn undoing_a_column_delete_restores_widths() {
+        let mut app = App::new();
+        app.set_cell(CellId::new(0, 1), "b".to_string());
+        app.set_cell(CellId::new(0, 2), "c".to_string());
+        app.set_column_width(1, 20);
+        app.set_column_width(2, 30);
+
+        app.delete_columns(1, 1);
+        assert_eq!(app.column_width(1), 30);
+        app.undo();
+        assert_eq!(
+            app.cells.get(&CellId::new(0, 1)).map(String::as_str),
+            Some("b")
+        );
+        assert_eq!((app.column_width(1), app.column_width(2)), (20, 30));
+        app.redo();
+        assert_eq!(app.column_width(1), 30);
+    }
+
+    #[test]
+    fn undoing_a_column_insert_restores_widths() {
+        let mut app = App::new();
+        app.set_column_width(0, 20);
+        app.insert_columns(0, 2);
+        assert_eq!(app.column_width(2), 20);
+        app.undo();
+        assert_eq!(app.column_width(0), 20);
+        assert_eq!(app.column_width(2), 12);
+    }
+
+    #[test]
+    fn undoing_a_column_delete_restores_formats() {
+        let mut app = App::new();
+        let column = |col| Selection {
+            top: 0,
+            left: col,
+            bottom: usize::MAX,
+            right: col,
+        };
+        let percent = NumberFormat::Percent { decimals: 0 };
+        let fixed = NumberFormat::Fixed {
+            decimals: 2,
+            thousands: false,
+        };
+        app.set_cell(CellId::new(0, 1), "0.5".to_string());
+        app.set_cell(CellId::new(0, 2), "3".to_string());
+        app.set_number_format(column(1), percent.clone());
+        app.set_number_format(column(2), fixed.clone());
+
+        app.delete_columns(1, 1);
+        assert_eq!(app.number_format(CellId::new(0, 1)), Some(&fixed));
+        app.undo();
+        assert_eq!(app.number_format(CellId::new(0, 1)), Some(&percent));
+        assert_eq!(app.number_format(CellId::new(0, 2)), Some(&fixed));
+        app.redo();
+        assert_eq!(app.number_format(CellId::new(0, 1)), Some(&fixed));
+    }
+
+    #[test]
+    fn undoing_a_row_insert_restores_formats() {
+        let mut app = App::new();
+        let row = Selection {
+            top: 2,
+            left: 0,
+            bottom: 2,
+            right: usize::MAX,
+        };
+        app.set_number_format(row, NumberFormat::Percent { decimals: 1 });
+        app.insert_rows(0, 3);
+        assert!(app.number_format(CellId::new(2, 0)).is_none());
+        app.undo();
+        assert!(app.number_format(CellId::new(2, 0)).is_some());
+    }
+
+    #[test]
+    fn undoing_a_column_delete_restores_formulas_on_other_sheets() {
+        let mut app = App::new();
+        app.set_cell(CellId::new(0, 1), "7".to_string());
+        app.handle_sheet_command(&["new", "Other"]);
+        app.set_cell(CellId::new(0, 0), "=Sheet1!B1".to_string());
+        app.switch_sheet(0);
+        let formula = |app: &mut App| {
+            app.switch_sheet(1);
+            let raw = app.cells[&CellId::new(0, 0)].clone();
+            let value = app.cell_value(CellId::new(0, 0));
+            app.switch_sheet(0);
+            (raw, value)
+        };
+
+        app.delete_columns(0, 0);
+        assert_eq!(
+            formula(&mut app),
+            ("=Sheet1!A1".to_string(), Value::Number(7.0))
+        );
+        app.undo();
+        assert_eq!(
+            formula(&mut app),
+            ("=Sheet1!B1".to_string(), Value::Number(7.0))
+        );
+        app.redo();
+        assert_eq!(formula(&mut app).0, "=Sheet1!A1");
+    }
+}
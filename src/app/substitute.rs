@@ -0,0 +1,356 @@
+//! `:[range]s/pattern/replacement/[flags]`, find and replace over the raw
+//! contents of cells, so formulas can be rewritten too.
+
+use std::sync::LazyLock;
+
+use regex::{Regex, RegexBuilder};
+
+use super::{App, CellId, Mode, formula, visual::Selection};
+
+/// The range prefix, the `s` and the delimiter. The delimiter may be any
+/// character that cannot start a command name, as in vim.
+static COMMAND: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(%|\$?[A-Za-z]+\$?[0-9]+(?::\$?[A-Za-z]+\$?[0-9]+)?)?s([^A-Za-z0-9\s\\"|])"#)
+        .expect("substitute command regex is valid")
+});
+
+/// A parsed substitute command.
+struct Substitute {
+    area: Option<Selection>,
+    pattern: String,
+    replacement: String,
+    global: bool,
+    confirm: bool,
+    ignore_case: bool,
+}
+
+/// A cell with at least one match, and what each match becomes.
+#[derive(Debug)]
+struct Candidate {
+    cell: CellId,
+    raw: String,
+    matches: Vec<Replacement>,
+}
+
+#[derive(Debug)]
+struct Replacement {
+    start: usize,
+    end: usize,
+    text: String,
+    accepted: bool,
+}
+
+/// A substitution waiting on `y/n/a/q/l` answers, one match at a time.
+#[derive(Debug)]
+pub(crate) struct Substitution {
+    candidates: Vec<Candidate>,
+    cell: usize,
+    index: usize,
+}
+
+impl App {
+    /// Runs `command` if it is a substitute and returns whether it was one.
+    pub(super) fn try_substitute_command(&mut self, command: &str) -> bool {
+        let Some(parsed) = parse(command) else {
+            return false;
+        };
+        if let Err(err) = parsed.and_then(|substitute| self.substitute(substitute)) {
+            self.command_buffer = err;
+        }
+        true
+    }
+
+    fn substitute(&mut self, substitute: Substitute) -> Result<(), String> {
+        let pattern = match substitute.pattern.as_str() {
+            "" => match &self.search {
+                Some(search) => regex::escape(search.pattern()),
+                None => return Err("E35: No previous search pattern".to_string()),
+            },
+            pattern => pattern.to_string(),
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(substitute.ignore_case)
+            .build()
+            .map_err(|err| match err {
+                regex::Error::Syntax(message) => format!(
+                    "invalid pattern: {}",
+                    message.lines().last().unwrap_or_default()
+                ),
+                err => format!("invalid pattern: {}", err),
+            })?;
+        let area = substitute
+            .area
+            .or(self.command_selection)
+            .unwrap_or(Selection {
+                top: self.cursor.row,
+                left: 0,
+                bottom: self.cursor.row,
+                right: usize::MAX,
+            });
+
+        let mut cells: Vec<(&CellId, &String)> = self
+            .cells
+            .iter()
+            .filter(|(cell, _)| area.contains(cell.row, cell.col))
+            .collect();
+        cells.sort_by_key(|(cell, _)| (cell.row, cell.col));
+        let candidates: Vec<Candidate> = cells
+            .into_iter()
+            .filter_map(|(cell, raw)| {
+                let matches: Vec<Replacement> = regex
+                    .captures_iter(raw)
+                    .take(if substitute.global { usize::MAX } else { 1 })
+                    .map(|caps| {
+                        let whole = caps.get(0).expect("group 0 always matches");
+                        let mut text = String::new();
+                        caps.expand(&substitute.replacement, &mut text);
+                        Replacement {
+                            start: whole.start(),
+                            end: whole.end(),
+                            text,
+                            accepted: !substitute.confirm,
+                        }
+                    })
+                    .collect();
+                (!matches.is_empty()).then(|| Candidate {
+                    cell: *cell,
+                    raw: raw.clone(),
+                    matches,
+                })
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Err(format!("E486: Pattern not found: {}", pattern));
+        }
+
+        self.substitution = Some(Substitution {
+            candidates,
+            cell: 0,
+            index: 0,
+        });
+        if substitute.confirm {
+            self.mode = Mode::Confirm;
+            self.show_substitution_prompt();
+        } else {
+            self.finish_substitution();
+        }
+        Ok(())
+    }
+
+    /// Handles an answer to the confirmation prompt: `y` replaces this
+    /// match, `n` skips it, `a` replaces it and all the rest, `l` replaces
+    /// it and stops, `q` stops.
+    pub(super) fn answer_substitution(&mut self, answer: char) {
+        let Some(substitution) = &mut self.substitution else {
+            self.enter_normal_mode();
+            return;
+        };
+        let (cell, index) = (substitution.cell, substitution.index);
+        let current = &mut substitution.candidates[cell].matches[index];
+        match answer {
+            'y' => current.accepted = true,
+            'n' => {}
+            'l' => {
+                current.accepted = true;
+                self.finish_substitution();
+                return;
+            }
+            'a' => {
+                for replacement in &mut substitution.candidates[cell].matches[index..] {
+                    replacement.accepted = true;
+                }
+                for candidate in &mut substitution.candidates[cell + 1..] {
+                    for replacement in &mut candidate.matches {
+                        replacement.accepted = true;
+                    }
+                }
+                self.finish_substitution();
+                return;
+            }
+            'q' => {
+                self.finish_substitution();
+                return;
+            }
+            _ => return,
+        }
+
+        substitution.index += 1;
+        if substitution.index == substitution.candidates[cell].matches.len() {
+            substitution.cell += 1;
+            substitution.index = 0;
+        }
+        if substitution.cell == substitution.candidates.len() {
+            self.finish_substitution();
+        } else {
+            self.show_substitution_prompt();
+        }
+    }
+
+    /// Moves to the match being asked about and shows the question.
+    fn show_substitution_prompt(&mut self) {
+        let Some(substitution) = &self.substitution else {
+            return;
+        };
+        let candidate = &substitution.candidates[substitution.cell];
+        let replacement = &candidate.matches[substitution.index];
+        let prompt = format!(
+            "replace \"{}\" with \"{}\" (y/n/a/q/l)?",
+            &candidate.raw[replacement.start..replacement.end],
+            replacement.text
+        );
+        let cell = candidate.cell;
+        self.move_cursor(
+            cell.col as i32 - self.cursor.col as i32,
+            cell.row as i32 - self.cursor.row as i32,
+        );
+        self.command_buffer = prompt;
+    }
+
+    /// Writes every accepted replacement as one undoable change.
+    fn finish_substitution(&mut self) {
+        let Some(substitution) = self.substitution.take() else {
+            return;
+        };
+        let mut writes = Vec::new();
+        let mut count = 0;
+        for candidate in substitution.candidates {
+            let mut out = String::with_capacity(candidate.raw.len());
+            let mut last = 0;
+            for replacement in candidate.matches.iter().filter(|m| m.accepted) {
+                out.push_str(&candidate.raw[last..replacement.start]);
+                out.push_str(&replacement.text);
+                last = replacement.end;
+                count += 1;
+            }
+            if candidate.matches.iter().any(|m| m.accepted) {
+                out.push_str(&candidate.raw[last..]);
+                writes.push((candidate.cell, out));
+            }
+        }
+        let cells = writes.len();
+        if !writes.is_empty() {
+            self.set_cells(writes);
+        }
+        self.enter_normal_mode();
+        self.command_buffer = format!(
+            "{} substitution{} in {} cell{}",
+            count,
+            plural(count),
+            cells,
+            plural(cells)
+        );
+    }
+}
+
+/// Parses a substitute command, or returns `None` when `command` is some
+/// other command.
+fn parse(command: &str) -> Option<Result<Substitute, String>> {
+    let caps = COMMAND.captures(command)?;
+    let delimiter = caps[2].chars().next()?;
+    let area = match caps.get(1).map(|range| range.as_str()) {
+        None => None,
+        Some("%") => Some(Selection {
+            top: 0,
+            left: 0,
+            bottom: usize::MAX,
+            right: usize::MAX,
+        }),
+        Some(range) => match parse_area(range) {
+            Some(area) => Some(area),
+            None => return Some(Err(format!("invalid range: {}", range))),
+        },
+    };
+
+    let mut parts = split_unescaped(&command[caps[0].len()..], delimiter).into_iter();
+    let pattern = parts.next().unwrap_or_default();
+    let replacement = parts.next().unwrap_or_default();
+    let flags = parts.next().unwrap_or_default();
+    if let Some(extra) = parts.next() {
+        return Some(Err(format!("E488: Trailing characters: {}", extra)));
+    }
+    let mut substitute = Substitute {
+        area,
+        pattern,
+        replacement: expand_template(&replacement),
+        global: false,
+        confirm: false,
+        ignore_case: false,
+    };
+    for flag in flags.chars() {
+        match flag {
+            'g' => substitute.global = true,
+            'c' => substitute.confirm = true,
+            'i' => substitute.ignore_case = true,
+            'I' => substitute.ignore_case = false,
+            other => return Some(Err(format!("E488: Trailing characters: {}", other))),
+        }
+    }
+    Some(Ok(substitute))
+}
+
+/// `A1:C20` or a single `B4`, in either corner order.
+fn parse_area(range: &str) -> Option<Selection> {
+    let (from, to) = range.split_once(':').unwrap_or((range, range));
+    let (from, to) = (
+        formula::parse_cell_label(from)?,
+        formula::parse_cell_label(to)?,
+    );
+    Some(Selection {
+        top: from.row.min(to.row),
+        left: from.col.min(to.col),
+        bottom: from.row.max(to.row),
+        right: from.col.max(to.col),
+    })
+}
+
+/// Splits on `delimiter`, turning `\<delimiter>` into the delimiter itself
+/// and leaving other escapes for the regex.
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("parts is never empty");
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => part.push('\\'),
+            },
+            c if c == delimiter => parts.push(String::new()),
+            c => part.push(c),
+        }
+    }
+    parts
+}
+
+/// Turns a vim replacement, where `&` and `\0` are the whole match and
+/// `\1`..`\9` are groups, into the `$` syntax the regex crate expands.
+fn expand_template(replacement: &str) -> String {
+    let mut out = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => out.push_str("${0}"),
+            '$' => out.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    out.push_str("${");
+                    out.push(digit);
+                    out.push('}');
+                }
+                Some('$') => out.push_str("$$"),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
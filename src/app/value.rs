@@ -0,0 +1,150 @@
+use std::fmt;
+
+use super::datetime::{self, DateKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Empty,
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    /// A serial date/time, remembering which parts were entered.
+    Date(f64, DateKind),
+    Error(CellError),
+}
+
+impl Value {
+    /// Infers the type of a value typed into a cell. A leading `'` keeps
+    /// the rest as text, so `'007` is not read as the number 7.
+    pub(crate) fn from_literal(raw: &str) -> Self {
+        if raw.is_empty() {
+            return Value::Empty;
+        }
+        if let Some(text) = raw.strip_prefix('\'') {
+            return Value::Text(text.to_string());
+        }
+        if let Some(number) = parse_number(raw) {
+            return Value::Number(number);
+        }
+        if let Some(flag) = parse_bool(raw) {
+            return Value::Bool(flag);
+        }
+        match datetime::parse(raw) {
+            Some((serial, kind)) => Value::Date(serial, kind),
+            None => Value::Text(raw.to_string()),
+        }
+    }
+
+    pub(crate) fn as_number(&self) -> Result<f64, CellError> {
+        match self {
+            Value::Empty => Ok(0.0),
+            Value::Number(number) | Value::Date(number, _) => Ok(*number),
+            Value::Bool(flag) => Ok(f64::from(u8::from(*flag))),
+            Value::Text(text) => parse_number(text).ok_or(CellError::Value),
+            Value::Error(err) => Err(*err),
+        }
+    }
+
+    /// Whether the value is shown right-aligned, as numbers are.
+    pub(crate) fn is_numeric(&self) -> bool {
+        matches!(self, Value::Number(_) | Value::Date(_, _))
+    }
+
+    pub(crate) fn as_text(&self) -> Result<String, CellError> {
+        match self {
+            Value::Error(err) => Err(*err),
+            other => Ok(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Empty => Ok(()),
+            Value::Number(number) => write!(f, "{}", format_number(*number)),
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Bool(flag) => write!(f, "{}", if *flag { "TRUE" } else { "FALSE" }),
+            Value::Date(serial, kind) => write!(f, "{}", datetime::format(*serial, *kind)),
+            Value::Error(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CellError {
+    Div0,
+    Ref,
+    Value,
+    Name,
+    Num,
+    Parse,
+    Cycle,
+}
+
+impl CellError {
+    pub(crate) const ALL: [CellError; 7] = [
+        CellError::Div0,
+        CellError::Ref,
+        CellError::Value,
+        CellError::Name,
+        CellError::Num,
+        CellError::Parse,
+        CellError::Cycle,
+    ];
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CellError::Div0 => write!(f, "#DIV/0!"),
+            CellError::Ref => write!(f, "#REF!"),
+            CellError::Value => write!(f, "#VALUE!"),
+            CellError::Name => write!(f, "#NAME?"),
+            CellError::Num => write!(f, "#NUM!"),
+            CellError::Parse => write!(f, "#ERROR!"),
+            CellError::Cycle => write!(f, "#CYCLE!"),
+        }
+    }
+}
+
+pub(crate) fn parse_number(raw: &str) -> Option<f64> {
+    let trimmed = raw.trim();
+    let looks_numeric = !trimmed.is_empty()
+        && trimmed
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'));
+    if !looks_numeric {
+        return None;
+    }
+    trimmed.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+fn parse_bool(raw: &str) -> Option<bool> {
+    let trimmed = raw.trim();
+    if trimmed.eq_ignore_ascii_case("TRUE") {
+        Some(true)
+    } else if trimmed.eq_ignore_ascii_case("FALSE") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn format_number(number: f64) -> String {
+    if number == 0.0 {
+        return "0".to_string();
+    }
+    let magnitude = number.abs();
+    if !(1e-9..1e15).contains(&magnitude) {
+        return format!("{:e}", number);
+    }
+    if number.fract() == 0.0 {
+        return format!("{}", number as i64);
+    }
+    let fixed = format!("{:.10}", number);
+    fixed
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
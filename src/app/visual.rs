@@ -0,0 +1,249 @@
+use super::{
+    App, CellId, Mode, VisualKind, VisualState,
+    clipboard::{Clipboard, ClipboardKind, paste_writes},
+    formula,
+};
+
+/// Inclusive block of cells covered by a visual selection. Row and column
+/// selections are unbounded along the other axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Selection {
+    pub(crate) top: usize,
+    pub(crate) left: usize,
+    pub(crate) bottom: usize,
+    pub(crate) right: usize,
+}
+
+impl Selection {
+    pub(crate) fn contains(&self, row: usize, col: usize) -> bool {
+        (self.top..=self.bottom).contains(&row) && (self.left..=self.right).contains(&col)
+    }
+}
+
+impl App {
+    pub(super) fn enter_visual_mode(&mut self, kind: VisualKind) {
+        self.mode = Mode::Visual(VisualState {
+            anchor: self.cursor,
+            kind,
+        });
+        self.command_buffer.clear();
+    }
+
+    /// Switches between `v`, `V` and `Ctrl-v`, leaving visual mode when the
+    /// same kind is requested again, like vim.
+    pub(super) fn toggle_visual_kind(&mut self, kind: VisualKind) {
+        match self.mode {
+            Mode::Visual(state) if state.kind == kind => self.mode = Mode::Normal,
+            Mode::Visual(state) => {
+                self.mode = Mode::Visual(VisualState {
+                    anchor: state.anchor,
+                    kind,
+                })
+            }
+            _ => self.enter_visual_mode(kind),
+        }
+    }
+
+    pub(super) fn swap_visual_anchor(&mut self) {
+        if let Mode::Visual(ref mut state) = self.mode {
+            std::mem::swap(&mut state.anchor, &mut self.cursor);
+        }
+        self.ensure_cursor_visible();
+    }
+
+    pub(super) fn selection(&self) -> Option<Selection> {
+        let Mode::Visual(state) = self.mode else {
+            return None;
+        };
+        let top = state.anchor.row.min(self.cursor.row);
+        let bottom = state.anchor.row.max(self.cursor.row);
+        let left = state.anchor.col.min(self.cursor.col);
+        let right = state.anchor.col.max(self.cursor.col);
+        Some(match state.kind {
+            VisualKind::Cell => Selection {
+                top,
+                left,
+                bottom,
+                right,
+            },
+            VisualKind::Row => Selection {
+                top,
+                left: 0,
+                bottom,
+                right: usize::MAX,
+            },
+            VisualKind::Column => Selection {
+                top: 0,
+                left,
+                bottom: usize::MAX,
+                right,
+            },
+        })
+    }
+
+    /// Shrinks unbounded row and column selections to the cells in use.
+    fn used_selection(&self, selection: Selection) -> Selection {
+        let inside = || {
+            self.cells
+                .keys()
+                .filter(move |cell| selection.contains(cell.row, cell.col))
+        };
+        let bottom = if selection.bottom == usize::MAX {
+            inside().map(|cell| cell.row).max().unwrap_or(selection.top)
+        } else {
+            selection.bottom
+        };
+        let right = if selection.right == usize::MAX {
+            inside()
+                .map(|cell| cell.col)
+                .max()
+                .unwrap_or(selection.left)
+        } else {
+            selection.right
+        };
+        Selection {
+            bottom: bottom.max(selection.top),
+            right: right.max(selection.left),
+            ..selection
+        }
+    }
+
+    fn leave_visual_mode(&mut self, selection: Selection) {
+        self.mode = Mode::Normal;
+        self.cursor.row = selection.top;
+        self.cursor.col = selection.left;
+        self.ensure_cursor_visible();
+    }
+
+    pub(super) fn yank_selection(&mut self) {
+        let Some(selection) = self.selection() else {
+            return;
+        };
+        let block = self.used_selection(selection);
+        let origin = CellId::new(block.top, block.left);
+        let clipboard = Clipboard {
+            rows: self.block_values(origin, CellId::new(block.bottom, block.right)),
+            origin,
+            kind: match self.mode {
+                Mode::Visual(VisualState {
+                    kind: VisualKind::Row,
+                    ..
+                }) => ClipboardKind::Rows,
+                _ => ClipboardKind::Block,
+            },
+        };
+        let count = clipboard.cell_count();
+        self.clipboard = Some(clipboard);
+        self.leave_visual_mode(selection);
+        self.command_buffer = format!("{} cells yanked", count);
+        self.publish_clipboard();
+    }
+
+    pub(super) fn delete_selection(&mut self) {
+        let Some(selection) = self.selection() else {
+            return;
+        };
+        match self.mode {
+            Mode::Visual(VisualState {
+                kind: VisualKind::Row,
+                ..
+            }) => {
+                self.delete_rows(selection.top, selection.bottom);
+                self.leave_visual_mode(selection);
+                return;
+            }
+            Mode::Visual(VisualState {
+                kind: VisualKind::Column,
+                ..
+            }) => {
+                self.delete_columns(selection.left, selection.right);
+                self.leave_visual_mode(selection);
+                return;
+            }
+            _ => {}
+        }
+
+        let writes: Vec<(CellId, String)> = self
+            .cells
+            .keys()
+            .filter(|cell| selection.contains(cell.row, cell.col))
+            .map(|cell| (*cell, String::new()))
+            .collect();
+        let count = writes.len();
+        self.set_cells(writes);
+        self.leave_visual_mode(selection);
+        self.command_buffer = format!("{} cells cleared", count);
+    }
+
+    /// Pastes the clipboard at the top-left of the selection. A single
+    /// yanked cell is repeated across the whole selection instead.
+    pub(super) fn paste_into_selection(&mut self) -> bool {
+        let Some(selection) = self.selection() else {
+            return false;
+        };
+        let Some(clip) = self.clipboard.clone() else {
+            self.leave_visual_mode(selection);
+            self.command_buffer = "clipboard empty".to_string();
+            return false;
+        };
+        let block = self.used_selection(selection);
+
+        if clip.height() == 1 && clip.width() == 1 {
+            let mut writes = Vec::new();
+            for row in block.top..=block.bottom {
+                for col in block.left..=block.right {
+                    writes.extend(paste_writes(&clip, CellId::new(row, col)));
+                }
+            }
+            self.set_cells(writes);
+        } else {
+            self.paste_block_at(&clip, CellId::new(block.top, block.left));
+        }
+        self.leave_visual_mode(selection);
+        true
+    }
+
+    /// Copies the first row of the selection into the rows below it.
+    pub(super) fn fill_selection_down(&mut self) {
+        let Some(selection) = self.selection() else {
+            return;
+        };
+        let block = self.used_selection(selection);
+        let mut writes = Vec::new();
+        for col in block.left..=block.right {
+            let source = self.cells.get(&CellId::new(block.top, col)).cloned();
+            let source = source.unwrap_or_default();
+            for row in block.top + 1..=block.bottom {
+                let offset = (row - block.top) as isize;
+                writes.push((
+                    CellId::new(row, col),
+                    formula::translate(&source, offset, 0),
+                ));
+            }
+        }
+        self.set_cells(writes);
+        self.leave_visual_mode(selection);
+    }
+
+    /// Copies the first column of the selection into the columns to its right.
+    pub(super) fn fill_selection_right(&mut self) {
+        let Some(selection) = self.selection() else {
+            return;
+        };
+        let block = self.used_selection(selection);
+        let mut writes = Vec::new();
+        for row in block.top..=block.bottom {
+            let source = self.cells.get(&CellId::new(row, block.left)).cloned();
+            let source = source.unwrap_or_default();
+            for col in block.left + 1..=block.right {
+                let offset = (col - block.left) as isize;
+                writes.push((
+                    CellId::new(row, col),
+                    formula::translate(&source, 0, offset),
+                ));
+            }
+        }
+        self.set_cells(writes);
+        self.leave_visual_mode(selection);
+    }
+}
@@ -1,12 +1,18 @@
 mod app;
 
+use std::env;
+
 use app::App;
 use color_eyre::Result;
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    let mut app = App::new();
+    if let Some(path) = env::args().nth(1) {
+        app.handle_open_command(path);
+    }
     let terminal = ratatui::init();
-    let result = App::new().run(terminal);
+    let result = app.run(terminal);
     ratatui::restore();
     result
 }